@@ -0,0 +1,75 @@
+use std::collections::HashMap;
+
+use super::faruledata::{FARuleData};
+use super::farule::{FARule};
+use super::symbol::{Symbol};
+
+/// A partition of the alphabet into classes of symbols that no rule tells
+/// apart, so transitions can be indexed by class instead of by symbol. The
+/// classes are numbered in the order of their first symbol.
+#[derive(Debug,Clone,PartialEq)]
+pub struct SymbolClasses<S = char> {
+    /// The first symbol of each run of symbols of one class, sorted.
+    starts: Vec<S>,
+    /// The class of each run.
+    classes: Vec<usize>,
+    len: usize,
+}
+
+impl<S: Symbol> SymbolClasses<S> {
+    pub fn new<T: Eq + Clone>(rules: &[FARule<T, S>]) -> Self {
+        let ranges = rules.iter().map(|rule| rule.ranges()).collect::<Vec<Vec<FARuleData<S>>>>();
+        let mut boundaries = vec![S::min_symbol()];
+        for data in ranges.iter().flatten() {
+            let (start, end) = data.bounds();
+            boundaries.push(start);
+            boundaries.extend(end.successor());
+        }
+        boundaries.sort();
+        boundaries.dedup();
+
+        let mut numbers: HashMap<Vec<usize>, usize> = HashMap::new();
+        let mut starts = Vec::new();
+        let mut classes: Vec<usize> = Vec::new();
+        for start in boundaries {
+            let reading = ranges.iter().enumerate()
+                .filter(|(_, ranges)| ranges.iter().any(|data| data.applies_to(&start)))
+                .map(|(i, _)| i)
+                .collect::<Vec<usize>>();
+            let next = numbers.len();
+            let class = *numbers.entry(reading).or_insert(next);
+            if classes.last() != Some(&class) {
+                starts.push(start);
+                classes.push(class);
+            }
+        }
+        SymbolClasses { starts, classes, len: numbers.len() }
+    }
+
+    pub fn class_of(&self, symbol: S) -> usize {
+        self.classes[self.starts.partition_point(|&start| start <= symbol) - 1]
+    }
+
+    /// The number of classes.
+    pub fn len(&self) -> usize { self.len }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+
+    /// The first symbol of `class`.
+    pub fn representative(&self, class: usize) -> S {
+        self.starts[self.classes.iter().position(|&c| c == class).unwrap()]
+    }
+
+    /// The symbols of `class` as sorted ranges.
+    pub fn ranges(&self, class: usize) -> Vec<FARuleData<S>> {
+        (0..self.starts.len())
+            .filter(|&run| self.classes[run] == class)
+            .map(|run| {
+                let end = match self.starts.get(run + 1) {
+                    Some(next) => next.predecessor().unwrap(),
+                    None => S::max_symbol(),
+                };
+                FARuleData::range(self.starts[run], end)
+            })
+            .collect()
+    }
+}
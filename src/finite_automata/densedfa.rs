@@ -0,0 +1,130 @@
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use helper::{to_hashset};
+
+use super::classes::{SymbolClasses};
+use super::nfadesign::{NFADesign};
+use super::subset::{SubsetError, subset_hash};
+use super::symbol::{Symbol};
+
+/// The state no input leads out of.
+pub const DEAD: u32 = 0;
+
+/// A DFA stored as a flat table with one row per state and one column per
+/// symbol class, for matching with a lookup per symbol. State 0 is the dead
+/// state, whose row leads back to it. An unanchored DFA can start a match at
+/// every position, so it tells whether some substring of the input is
+/// accepted, and never reaches the dead state.
+#[derive(Debug,Clone)]
+pub struct DenseDFA<S = char> {
+    classes: SymbolClasses<S>,
+    table: Vec<u32>,
+    accepting: Vec<bool>,
+    start: u32,
+}
+
+impl<S: Symbol> DenseDFA<S> {
+    /// Build the DFA of `design` by the subset construction over its symbol
+    /// classes, failing past `limit` states or on `Look` rules.
+    pub fn new<T: Eq + Clone + Hash>(design: &NFADesign<T, S>, anchored: bool, limit: usize)
+                                     -> Result<Self, SubsetError> {
+        if design.has_looks() {
+            return Err(SubsetError::LookRules);
+        }
+        let classes = design.symbol_classes();
+        let stride = classes.len();
+        let rulebook = design.rulebook();
+        let accept_states = design.accept_state();
+        let start_state = rulebook.follow_free_moves(&to_hashset(&[design.start_state()]));
+
+        let mut subsets = vec![HashSet::new()];
+        let mut found: HashMap<u64, Vec<usize>> = HashMap::new();
+        found.entry(subset_hash(&subsets[0])).or_default().push(0);
+        let mut table = vec![DEAD; stride];
+        let mut accepting = vec![false];
+        // The row of the dead state stays all DEAD, even where an unanchored
+        // DFA would start a match.
+        let mut i = 1;
+        let mut add = |subset: HashSet<T>, subsets: &mut Vec<HashSet<T>>, table: &mut Vec<u32>,
+                       accepting: &mut Vec<bool>| -> Result<u32, SubsetError> {
+            let candidates = found.entry(subset_hash(&subset)).or_default();
+            if let Some(&id) = candidates.iter().find(|&&id| subsets[id] == subset) {
+                return Ok(id as u32);
+            }
+            if subsets.len() >= limit {
+                return Err(SubsetError::TooManyStates(limit));
+            }
+            candidates.push(subsets.len());
+            accepting.push(!subset.is_disjoint(&accept_states));
+            table.extend(vec![DEAD; stride]);
+            subsets.push(subset);
+            Ok(subsets.len() as u32 - 1)
+        };
+        let start = add(start_state.clone(), &mut subsets, &mut table, &mut accepting)?;
+        while i < subsets.len() {
+            for class in 0..stride {
+                let next_states = rulebook.next_states(&subsets[i], Some(classes.representative(class)));
+                let mut next_states = rulebook.follow_free_moves(&next_states);
+                if !anchored {
+                    next_states.extend(start_state.iter().cloned());
+                }
+                table[i * stride + class] = add(next_states, &mut subsets, &mut table, &mut accepting)?;
+            }
+            i += 1;
+        }
+        Ok(DenseDFA { classes, table, accepting, start })
+    }
+
+    /// The number of states, the dead state included.
+    pub fn len(&self) -> usize { self.accepting.len() }
+    pub fn is_empty(&self) -> bool { self.accepting.is_empty() }
+
+    pub fn start_state(&self) -> u32 { self.start }
+    pub fn is_accepting(&self, state: u32) -> bool { self.accepting[state as usize] }
+
+    pub fn next_state(&self, state: u32, symbol: S) -> u32 {
+        self.table[state as usize * self.classes.len() + self.classes.class_of(symbol)]
+    }
+
+    /// Whether the whole of `symbols` is accepted.
+    pub fn accept_symbols<I: IntoIterator<Item=S>>(&self, symbols: I) -> bool {
+        let mut state = self.start;
+        for symbol in symbols {
+            state = self.next_state(state, symbol);
+            if state == DEAD {
+                return false;
+            }
+        }
+        self.is_accepting(state)
+    }
+
+    /// Whether an accept state is reached on some prefix of `symbols`. Only
+    /// an anchored DFA stops early, at the dead state.
+    pub fn is_match_symbols<I: IntoIterator<Item=S>>(&self, symbols: I) -> bool {
+        let mut state = self.start;
+        if self.is_accepting(state) {
+            return true;
+        }
+        for symbol in symbols {
+            state = self.next_state(state, symbol);
+            if self.is_accepting(state) {
+                return true;
+            }
+            if state == DEAD {
+                return false;
+            }
+        }
+        false
+    }
+}
+
+impl DenseDFA {
+    pub fn accept(&self, s: &str) -> bool {
+        self.accept_symbols(s.chars())
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        self.is_match_symbols(s.chars())
+    }
+}
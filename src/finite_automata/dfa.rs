@@ -1,38 +1,62 @@
+use std::fmt::Debug;
+use std::hash::Hash;
+
 use super::dfarulebook::{DFARulebook};
+use super::predicate::{Predicate, RangeSet};
+use super::snapshot::{Snapshot, SnapshotError, StateIds};
 
 #[derive(Clone)]
-pub struct DFA<T> {
+pub struct DFA<T, S = char, P = RangeSet<S>> {
     current_state: T,
     accept_states: Vec<T>,
-    rulebook: DFARulebook<T>,
+    rulebook: DFARulebook<T, S, P>,
 }
 
-impl<T: Eq + Clone> DFA<T> {
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug, P: Predicate<S>> DFA<T, S, P> {
+    #[allow(clippy::ptr_arg)]
     pub fn new(current_state: T,
                accept_states: &Vec<T>,
-               rulebook: &DFARulebook<T>) -> Self {
+               rulebook: &DFARulebook<T, S, P>) -> Self {
         DFA {
-            current_state: current_state,
+            current_state,
             accept_states: accept_states.clone(),
             rulebook: rulebook.clone()
         }
     }
 
     pub fn accepting(&self) -> bool {
-        match self.accept_states.iter()
-                                .find(|x| x == &&self.current_state) {
-            Some(_) => true,
-            None => false,
-        }
+        self.accept_states.contains(&self.current_state)
     }
 
-    pub fn read_character(&mut self, character: char) {
+    pub fn read_character(&mut self, character: S) {
         self.current_state = self.rulebook.next_state(&self.current_state, character);
     }
 
-    pub fn read_string(&mut self, s: &str) {
-        for c in s.chars() {
+    pub fn read_symbols<I: IntoIterator<Item=S>>(&mut self, symbols: I) {
+        for c in symbols {
             self.read_character(c);
         }
     }
 }
+
+impl<T: Eq + Clone + Hash> DFA<T> {
+    pub fn read_string(&mut self, s: &str) {
+        self.read_symbols(s.chars());
+    }
+}
+
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug, P: Predicate<S>> DFA<T, S, P> {
+    pub fn snapshot(&self, ids: &StateIds<T, S, P>) -> Result<Snapshot, SnapshotError> {
+        ids.snapshot(Some(&self.current_state))
+    }
+
+    /// Continue from the state recorded in `snapshot`.
+    pub fn restore(&mut self, ids: &StateIds<T, S, P>, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        let mut states = ids.restore(snapshot)?;
+        if states.len() != 1 {
+            return Err(SnapshotError::Malformed);
+        }
+        self.current_state = states.remove(0);
+        Ok(())
+    }
+}
@@ -1,24 +1,186 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Debug;
+use std::hash::Hash;
+
 use super::dfa::{DFA};
 use super::dfarulebook::{DFARulebook};
+use super::farule::{FARule};
+use super::predicate::{Predicate, RangeSet, minterms};
+use super::snapshot::{StateIds};
+use super::symbol::{Symbol};
 
-pub struct DFADesign<T> {
+pub struct DFADesign<T, S = char, P = RangeSet<S>> {
     start_state: T,
     accept_states: Vec<T>,
-    rulebook: DFARulebook<T>,
+    rulebook: DFARulebook<T, S, P>,
 }
 
-impl<T: Eq + Clone> DFADesign<T> {
-    pub fn new(start_state: T, accept_states: &Vec<T>, rulebook: &DFARulebook<T>) -> Self {
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug, P: Predicate<S>> DFADesign<T, S, P> {
+    #[allow(clippy::ptr_arg)]
+    pub fn new(start_state: T, accept_states: &Vec<T>, rulebook: &DFARulebook<T, S, P>) -> Self {
         DFADesign {
-            start_state: start_state,
+            start_state,
             accept_states: accept_states.clone(),
             rulebook: rulebook.clone()}
     }
 
-    pub fn accept(&self, s: &str) -> bool {
-        let mut dfa = DFA::new(
-            self.start_state.clone(), &self.accept_states, &self.rulebook);
-        dfa.read_string(s);
+    pub fn to_dfa(&self) -> DFA<T, S, P> {
+        DFA::new(self.start_state.clone(), &self.accept_states, &self.rulebook)
+    }
+
+    pub fn accept_symbols<I: IntoIterator<Item=S>>(&self, symbols: I) -> bool {
+        let mut dfa = self.to_dfa();
+        dfa.read_symbols(symbols);
         dfa.accepting()
     }
+
+    pub fn start_state(&self) -> T { self.start_state.clone() }
+    pub fn accept_states(&self) -> Vec<T> { self.accept_states.clone() }
+    pub fn rulebook(&self) -> DFARulebook<T, S, P> { self.rulebook.clone() }
+}
+
+impl<T: Eq + Clone + Hash> DFADesign<T> {
+    pub fn accept(&self, s: &str) -> bool {
+        self.accept_symbols(s.chars())
+    }
+}
+
+impl<T: Eq + Clone + Hash, S: Symbol> DFADesign<T, S> {
+    /// Stable ids of the states, to snapshot and restore a running `DFA`.
+    pub fn state_ids(&self) -> StateIds<T, S> {
+        StateIds::new(&self.start_state, &self.accept_states, &self.rulebook.rules())
+    }
+}
+
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug, P: Predicate<S>> DFADesign<T, S, P> {
+    /// The equivalent DFA with the fewest states: unreachable states are
+    /// dropped and equivalent ones merged by Hopcroft's partition
+    /// refinement over the minterms of the guards. States are numbered
+    /// breadth-first from the start state, each with one rule per state it
+    /// moves to. Symbols the original had no rule for still have none.
+    pub fn minimize(&self) -> DFADesign<usize, S, P> {
+        // Number the reachable states, with their rules in rulebook order.
+        let mut ids: HashMap<T, usize> = HashMap::new();
+        let mut states = vec![self.start_state.clone()];
+        ids.insert(self.start_state.clone(), 0);
+        let mut rules: Vec<&FARule<T, S, P>> = Vec::new();
+        let mut i = 0;
+        while i < states.len() {
+            for rule in self.rulebook.rules_from(&states[i]) {
+                rules.push(rule);
+                if !ids.contains_key(&rule.next_state) {
+                    ids.insert(rule.next_state.clone(), states.len());
+                    states.push(rule.next_state.clone());
+                }
+            }
+            i += 1;
+        }
+
+        // The transition table over the minterms, with a sink standing for
+        // the missing rules.
+        let guards = rules.iter()
+            .map(|rule| rule.guard().unwrap_or_else(P::bottom))
+            .collect::<Vec<P>>();
+        let classes = minterms(&guards);
+        let sink = states.len();
+        let mut delta = vec![vec![sink; classes.len()]; sink + 1];
+        for (m, (_, satisfied)) in classes.iter().enumerate() {
+            for &g in satisfied.iter().rev() {
+                let rule = rules[g];
+                delta[ids[&rule.state]][m] = ids[&rule.next_state];
+            }
+        }
+        let mut inverse = vec![vec![Vec::new(); sink + 1]; classes.len()];
+        for (state, moves) in delta.iter().enumerate() {
+            for (m, &next) in moves.iter().enumerate() {
+                inverse[m][next].push(state);
+            }
+        }
+
+        // Refine the partition into accepting and other states.
+        let accepting = (0..=sink)
+            .map(|state| state < sink && self.accept_states.contains(&states[state]))
+            .collect::<Vec<bool>>();
+        let mut blocks: Vec<Vec<usize>> = vec![
+            (0..=sink).filter(|&state| accepting[state]).collect(),
+            (0..=sink).filter(|&state| !accepting[state]).collect(),
+        ];
+        blocks.retain(|block| !block.is_empty());
+        let mut block_of = vec![0; sink + 1];
+        for (b, block) in blocks.iter().enumerate() {
+            for &state in block {
+                block_of[state] = b;
+            }
+        }
+        let mut pending = (0..blocks.len()).collect::<Vec<usize>>();
+        let mut is_pending = vec![true; blocks.len()];
+        while let Some(splitter) = pending.pop() {
+            is_pending[splitter] = false;
+            // The splitter itself can be split by an earlier minterm.
+            let splitter_states = blocks[splitter].clone();
+            for moves_into in inverse.iter() {
+                let mut touched: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+                for &target in splitter_states.iter() {
+                    for &state in moves_into[target].iter() {
+                        touched.entry(block_of[state]).or_default().push(state);
+                    }
+                }
+                for (b, moved) in touched {
+                    if moved.len() == blocks[b].len() {
+                        continue;
+                    }
+                    let split = blocks.len();
+                    for &state in moved.iter() {
+                        block_of[state] = split;
+                    }
+                    blocks[b].retain(|&state| block_of[state] == b);
+                    blocks.push(moved);
+                    if is_pending[b] || blocks[split].len() <= blocks[b].len() {
+                        pending.push(split);
+                        is_pending.push(true);
+                    } else {
+                        pending.push(b);
+                        is_pending[b] = true;
+                        is_pending.push(false);
+                    }
+                }
+            }
+        }
+
+        // Number the blocks breadth-first and join the minterms leading
+        // from one block to another into a single guard.
+        let only_sink = |b: usize| blocks[b] == [sink];
+        let mut numbers: HashMap<usize, usize> = HashMap::new();
+        numbers.insert(block_of[0], 0);
+        let mut order = vec![block_of[0]];
+        let mut minimal_rules = Vec::new();
+        let mut i = 0;
+        while i < order.len() {
+            let representative = blocks[order[i]][0];
+            let mut moves: Vec<(usize, P)> = Vec::new();
+            for (m, (class, _)) in classes.iter().enumerate() {
+                let target = block_of[delta[representative][m]];
+                if only_sink(target) {
+                    continue;
+                }
+                match moves.iter_mut().find(|(b, _)| *b == target) {
+                    Some(entry) => entry.1 = entry.1.or(class),
+                    None => moves.push((target, class.clone())),
+                }
+            }
+            for (target, guard) in moves {
+                let next = *numbers.entry(target).or_insert_with(|| {
+                    order.push(target);
+                    order.len() - 1
+                });
+                minimal_rules.push(FARule::new_rulepredicate(&i, guard, &next));
+            }
+            i += 1;
+        }
+        let accept_states = order.iter().enumerate()
+            .filter(|&(_, &b)| accepting[blocks[b][0]])
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        DFADesign::new(0, &accept_states, &DFARulebook::new(minimal_rules))
+    }
 }
@@ -1,23 +1,45 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::hash::Hash;
+
 use super::farule::FARule;
+use super::predicate::{Predicate, RangeSet};
 
+/// The rules of a DFA, indexed by the state they leave.
 #[derive(Clone)]
-pub struct DFARulebook<T> {
-    rules: Vec<FARule<T>>,
+pub struct DFARulebook<T, S = char, P = RangeSet<S>> {
+    rules: Vec<FARule<T, S, P>>,
+    transitions: HashMap<T, Vec<usize>>,
 }
 
-impl<T: Eq + Clone> DFARulebook<T> {
-    pub fn new(rules: Vec<FARule<T>>) -> Self {
-        DFARulebook{rules: rules}
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug, P: Predicate<S>> DFARulebook<T, S, P> {
+    pub fn new(rules: Vec<FARule<T, S, P>>) -> Self {
+        let mut transitions: HashMap<T, Vec<usize>> = HashMap::new();
+        for (i, rule) in rules.iter().enumerate() {
+            transitions.entry(rule.state.clone()).or_default().push(i);
+        }
+        DFARulebook{rules, transitions}
     }
 
-    pub fn next_state(&self, state: &T, character: char) -> T {
+    pub fn next_state(&self, state: &T, character: S) -> T {
         match self.rule_for(state, character) {
             Some(rule) => rule.follow(),
             None => panic!("No valid next state"),
         }
     }
 
-    pub fn rule_for(&self, state: &T, character: char) -> Option<&FARule<T>> {
-        self.rules.iter().find(|x| x.applies_to(state, Some(character)))
+    pub fn rules(&self) -> Vec<FARule<T, S, P>> { self.rules.clone() }
+
+    /// The rules leaving `state`, in the order they were given.
+    pub fn rules_from(&self, state: &T) -> Vec<&FARule<T, S, P>> {
+        self.transitions.get(state).map_or(Vec::new(), |indices| {
+            indices.iter().map(|&i| &self.rules[i]).collect()
+        })
+    }
+
+    pub fn rule_for(&self, state: &T, character: S) -> Option<&FARule<T, S, P>> {
+        self.transitions.get(state)?.iter()
+            .map(|&i| &self.rules[i])
+            .find(|rule| rule.applies_to(state, Some(character)))
     }
 }
@@ -1,30 +1,42 @@
 use super::faruledata::FARuleData;
+use super::look::{LineTerminator, Look};
+use super::predicate::{Predicate, RangeSet};
+use super::symbol::{Symbol};
+use std::fmt::Debug;
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
 
+/// A transition between states of type `T`, reading symbols of type `S`.
+/// Predicate rules are guarded by a `P`.
 #[derive(Debug,Clone)]
-pub struct FARule<T> {
+pub struct FARule<T, S = char, P = RangeSet<S>> {
     pub state: T,
     pub next_state: T,
-    kind: FARuleType
+    kind: FARuleType<S, P>
 }
 
 #[derive(Debug,Clone,PartialEq)]
-enum FARuleType {
-    RuleChar { character: char },
+#[allow(clippy::enum_variant_names)]
+enum FARuleType<S, P> {
+    RuleChar { character: S },
     RuleFree,
+    RuleTag { tag: usize },
     RuleAny,
-    RuleSet { set: Vec<FARuleData>, reverse: bool },
+    RuleSet { set: Vec<FARuleData<S>>, reverse: bool },
+    RulePredicate { predicate: P },
+    /// `line` pairs the symbols of the line terminators `look` looks for
+    /// with their characters.
+    RuleLook { look: Look, line: Vec<(S, char)> },
 }
 
-impl<T: Eq + PartialEq + Clone> FARule<T> {
-    pub fn new_rulechar(state: &T, character: char, next_state: &T) -> Self {
+impl<T: Eq + Clone, S: Copy + Ord> FARule<T, S> {
+    pub fn new_rulechar(state: &T, character: S, next_state: &T) -> Self {
         FARule {
             state: state.clone(),
             next_state: next_state.clone(),
             kind: FARuleType::RuleChar {
-                character: character
+                character
             }
         }
     }
@@ -37,6 +49,16 @@ impl<T: Eq + PartialEq + Clone> FARule<T> {
         }
     }
 
+    pub fn new_ruletag(state: &T, tag: usize, next_state: &T) -> Self {
+        FARule {
+            state: state.clone(),
+            next_state: next_state.clone(),
+            kind: FARuleType::RuleTag {
+                tag
+            }
+        }
+    }
+
     pub fn new_ruleany(state: &T, next_state: &T) -> Self {
         FARule {
             state: state.clone(),
@@ -45,28 +67,112 @@ impl<T: Eq + PartialEq + Clone> FARule<T> {
         }
     }
 
-    pub fn new_ruleset(state: &T, next_state: &T, set: &Vec<FARuleData>, reverse: bool) -> Self {
+    #[allow(clippy::ptr_arg)]
+    pub fn new_ruleset(state: &T, next_state: &T, set: &Vec<FARuleData<S>>, reverse: bool) -> Self {
         FARule {
             state: state.clone(),
             next_state: next_state.clone(),
             kind: FARuleType::RuleSet {
                 set: set.clone(),
-                reverse: reverse
+                reverse
+            }
+        }
+    }
+
+    /// The same rule in an automaton whose predicate rules are guarded by
+    /// `Q`.
+    pub fn into_guarded<Q: Predicate<S>>(self) -> FARule<T, S, Q> {
+        let kind = match self.kind {
+            FARuleType::RuleChar { character } => FARuleType::RuleChar { character },
+            FARuleType::RuleFree => FARuleType::RuleFree,
+            FARuleType::RuleTag { tag } => FARuleType::RuleTag { tag },
+            FARuleType::RuleAny => FARuleType::RuleAny,
+            FARuleType::RuleSet { set, reverse } => FARuleType::RuleSet { set, reverse },
+            FARuleType::RulePredicate { predicate } => FARuleType::RulePredicate {
+                predicate: Q::from_ranges(predicate.ranges())
+            },
+            FARuleType::RuleLook { look, line } => FARuleType::RuleLook { look, line },
+        };
+        FARule {
+            state: self.state,
+            next_state: self.next_state,
+            kind
+        }
+    }
+}
+
+impl<T: Eq + PartialEq + Clone, S: Copy + Ord + Debug, P: Predicate<S>> FARule<T, S, P> {
+    pub fn new_rulepredicate(state: &T, predicate: P, next_state: &T) -> Self {
+        FARule {
+            state: state.clone(),
+            next_state: next_state.clone(),
+            kind: FARuleType::RulePredicate {
+                predicate
             }
         }
     }
 
-    pub fn applies_to(&self, state: &T, c: Option<char>) -> bool {
+    pub fn applies_to(&self, state: &T, c: Option<S>) -> bool {
         self.state == *state && match c {
             Some(c) => match self.kind {
                 FARuleType::RuleChar { character } => character == c,
-                FARuleType::RuleFree => false,
+                FARuleType::RuleFree | FARuleType::RuleTag { .. } | FARuleType::RuleLook { .. } => false,
                 FARuleType::RuleAny => true,
                 FARuleType::RuleSet { ref set, reverse } => {
                     reverse ^ set.iter().any(|data| data.applies_to(&c))
                 }
+                FARuleType::RulePredicate { ref predicate } => predicate.accepts(&c),
+            }
+            None => self.is_free()
+        }
+    }
+
+    /// The symbols this rule consumes as a guard, `None` for free moves.
+    pub fn guard(&self) -> Option<P> {
+        match self.kind {
+            FARuleType::RuleChar { character } => Some(P::from_ranges(&[FARuleData::char(character)])),
+            FARuleType::RuleFree | FARuleType::RuleTag { .. } | FARuleType::RuleLook { .. } => None,
+            FARuleType::RuleAny => Some(P::top()),
+            FARuleType::RuleSet { ref set, reverse } => {
+                let guard = P::from_ranges(set);
+                Some(if reverse { guard.not() } else { guard })
             }
-            None => self.kind == FARuleType::RuleFree
+            FARuleType::RulePredicate { ref predicate } => Some(predicate.clone()),
+        }
+    }
+
+    pub fn is_free(&self) -> bool {
+        matches!(self.kind, FARuleType::RuleFree | FARuleType::RuleTag { .. })
+    }
+
+    pub fn look(&self) -> Option<Look> {
+        match self.kind {
+            FARuleType::RuleLook { look, .. } => Some(look),
+            _ => None,
+        }
+    }
+
+    /// The line terminator symbols of a `Look` rule with their characters.
+    pub fn line_symbols(&self) -> &[(S, char)] {
+        match self.kind {
+            FARuleType::RuleLook { ref line, .. } => line,
+            _ => &[],
+        }
+    }
+
+    pub fn tag(&self) -> Option<usize> {
+        match self.kind {
+            FARuleType::RuleTag { tag } => Some(tag),
+            _ => None,
+        }
+    }
+
+    /// The same rule between other states.
+    pub fn with_states<U>(&self, state: U, next_state: U) -> FARule<U, S, P> {
+        FARule {
+            state,
+            next_state,
+            kind: self.kind.clone()
         }
     }
 
@@ -75,16 +181,76 @@ impl<T: Eq + PartialEq + Clone> FARule<T> {
     }
 }
 
-impl<T: Display> Display for FARule<T> {
+impl<T: Eq + Clone, S: Symbol> FARule<T, S> {
+    /// A move reading nothing, only taken where `look` holds.
+    pub fn new_rulelook(state: &T, look: Look, next_state: &T) -> Self {
+        FARule {
+            state: state.clone(),
+            next_state: next_state.clone(),
+            kind: FARuleType::RuleLook {
+                look,
+                line: look.line_characters().iter()
+                    .filter_map(|&c| S::from_char(c).map(|symbol| (symbol, c)))
+                    .collect()
+            }
+        }
+    }
+
+    /// The symbols this rule consumes as merged ranges, empty for free
+    /// moves.
+    pub fn ranges(&self) -> Vec<FARuleData<S>> {
+        match self.kind {
+            FARuleType::RuleChar { character } => vec![FARuleData::char(character)],
+            FARuleType::RuleFree | FARuleType::RuleTag { .. } | FARuleType::RuleLook { .. } => vec![],
+            FARuleType::RuleAny => vec![FARuleData::range(S::min_symbol(), S::max_symbol())],
+            FARuleType::RuleSet { ref set, reverse: false } => FARuleData::merge(set),
+            FARuleType::RuleSet { ref set, reverse: true } => FARuleData::complement(set),
+            FARuleType::RulePredicate { ref predicate } => predicate.ranges().to_vec(),
+        }
+    }
+
+    /// What the rule does as numbers, a consuming rule by the codes of the
+    /// ranges it reads. They stay the same as long as the rule does, so
+    /// fingerprints are built from them.
+    pub fn codes(&self) -> Vec<u64> {
+        match self.kind {
+            FARuleType::RuleFree => vec![1],
+            FARuleType::RuleTag { tag } => vec![2, tag as u64],
+            FARuleType::RuleLook { look, .. } => vec![3, match look {
+                Look::StartText => 0,
+                Look::EndText => 1,
+                Look::StartLine(LineTerminator::LF) => 2,
+                Look::StartLine(LineTerminator::CRLF) => 3,
+                Look::EndLine(LineTerminator::LF) => 4,
+                Look::EndLine(LineTerminator::CRLF) => 5,
+            }],
+            _ => {
+                let ranges = self.ranges();
+                let mut codes = vec![0, ranges.len() as u64];
+                for data in ranges {
+                    let (start, end) = data.bounds();
+                    codes.push(start.code());
+                    codes.push(end.code());
+                }
+                codes
+            },
+        }
+    }
+}
+
+impl<T: Display, S: Display, P: Debug> Display for FARule<T, S, P> {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        let describe = match self.kind {
+        let describe = match &self.kind {
             FARuleType::RuleChar { character } => character.to_string(),
             FARuleType::RuleFree => "free".to_string(),
+            FARuleType::RuleTag { tag } => format!("tag {}", tag),
             FARuleType::RuleAny => "any".to_string(),
-            FARuleType::RuleSet { ref set, reverse } => {
-                format!("[{}{}]", if reverse {"^"} else {""},
+            FARuleType::RuleSet { set, reverse } => {
+                format!("[{}{}]", if *reverse {"^"} else {""},
                     set.iter().map(|data| format!("{}", data)).collect::<Vec<String>>().join(""))
             }
+            FARuleType::RulePredicate { predicate } => format!("{:?}", predicate),
+            FARuleType::RuleLook { look, .. } => format!("look {}", look),
         };
         write!(f, "FARule {} --{}--> {}", self.state, describe, self.next_state)
     }
@@ -2,30 +2,92 @@ use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
 
+use super::symbol::{Symbol};
+
 #[derive(Debug,Clone,PartialEq)]
-pub enum FARuleData {
-    Char {character: char},
-    Range {start: char, end: char},
+pub enum FARuleData<S = char> {
+    Char {character: S},
+    Range {start: S, end: S},
 }
 
-impl FARuleData {
-    pub fn char(c: char) -> Self {
+impl<S: Copy + Ord> FARuleData<S> {
+    pub fn char(c: S) -> Self {
         FARuleData::Char { character: c }
     }
 
-    pub fn range(start: char, end: char) -> Self {
-        FARuleData::Range { start: start, end: end }
+    pub fn range(start: S, end: S) -> Self {
+        FARuleData::Range { start, end }
     }
 
-    pub fn applies_to(&self, c: &char) -> bool {
+    pub fn applies_to(&self, c: &S) -> bool {
         match self {
             FARuleData::Char { character } => character == c,
             FARuleData::Range { start, end } => start <= c && c <= end
         }
     }
+
+    /// The first and last symbol covered.
+    pub fn bounds(&self) -> (S, S) {
+        match *self {
+            FARuleData::Char { character } => (character, character),
+            FARuleData::Range { start, end } => (start, end),
+        }
+    }
+
+    fn from_bounds(start: S, end: S) -> Self {
+        if start == end {
+            FARuleData::char(start)
+        } else {
+            FARuleData::range(start, end)
+        }
+    }
+
 }
 
-impl Display for FARuleData {
+impl<S: Symbol> FARuleData<S> {
+    /// Sort `set` and merge overlapping or adjacent ranges, so that every
+    /// symbol is covered at most once.
+    pub fn merge(set: &[FARuleData<S>]) -> Vec<FARuleData<S>> {
+        let mut bounds = set.iter().map(|data| data.bounds())
+                            .filter(|&(start, end)| start <= end)
+                            .collect::<Vec<_>>();
+        bounds.sort();
+        let mut merged: Vec<(S, S)> = Vec::new();
+        for (start, end) in bounds {
+            if let Some(last) = merged.last_mut() {
+                if last.1.successor().map_or(true, |next| start <= next) {
+                    if end > last.1 {
+                        last.1 = end;
+                    }
+                    continue;
+                }
+            }
+            merged.push((start, end));
+        }
+        merged.into_iter().map(|(start, end)| FARuleData::from_bounds(start, end)).collect()
+    }
+
+    /// Every symbol not covered by `set`.
+    pub fn complement(set: &[FARuleData<S>]) -> Vec<FARuleData<S>> {
+        let mut complement = Vec::new();
+        let mut next = Some(S::min_symbol());
+        for data in FARuleData::merge(set) {
+            let (start, end) = data.bounds();
+            if let Some(from) = next {
+                if from < start {
+                    complement.push(FARuleData::from_bounds(from, start.predecessor().unwrap()));
+                }
+            }
+            next = end.successor();
+        }
+        if let Some(from) = next {
+            complement.push(FARuleData::from_bounds(from, S::max_symbol()));
+        }
+        complement
+    }
+}
+
+impl<S: Display> Display for FARuleData<S> {
     fn fmt(&self, f: &mut Formatter) -> Result {
         match self {
             FARuleData::Char { character } => write!(f, "{}", character),
@@ -33,4 +95,3 @@ impl Display for FARuleData {
         }
     }
 }
-
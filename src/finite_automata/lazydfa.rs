@@ -0,0 +1,223 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
+use std::hash::Hash;
+use std::mem::size_of;
+
+use helper::{to_hashset};
+
+use super::nfadesign::{NFADesign};
+use super::nfarulebook::{NFARulebook};
+use super::predicate::{Predicate, RangeSet};
+use super::subset::{SubsetError, subset_hash};
+
+/// Limits of the state cache of a `LazyDFA`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct LazyDFAOptions {
+    /// Roughly how many bytes the cached states and transitions may take
+    /// before the cache is cleared.
+    pub cache_capacity: usize,
+    /// How many times the cache may be cleared before the automaton gives up
+    /// on caching and simulates the NFA instead.
+    pub max_cache_clears: usize,
+}
+
+impl Default for LazyDFAOptions {
+    fn default() -> Self {
+        LazyDFAOptions {
+            cache_capacity: 1 << 20,
+            max_cache_clears: 8,
+        }
+    }
+}
+
+/// A DFA built from an NFA while reading: each set of NFA states met gets a
+/// DFA state and each transition taken is remembered, so input already seen
+/// costs one lookup per symbol. `Look` rules are not followed.
+#[derive(Clone)]
+pub struct LazyDFA<T, S = char, P = RangeSet<S>> {
+    start_state: T,
+    accept_states: HashSet<T>,
+    rulebook: NFARulebook<T, S, P>,
+    options: LazyDFAOptions,
+    cache: Cache<T, S>,
+    current_state: Current<T>,
+    cache_clears: usize,
+}
+
+#[derive(Clone)]
+enum Current<T> {
+    Cached(usize),
+    /// The NFA states, closed under free moves.
+    Fallback(HashSet<T>),
+}
+
+#[derive(Clone)]
+struct Cache<T, S> {
+    subsets: Vec<HashSet<T>>,
+    accepting: Vec<bool>,
+    found: HashMap<u64, Vec<usize>>,
+    transitions: HashMap<(usize, S), usize>,
+    bytes: usize,
+}
+
+impl<T: Eq + Hash, S: Eq + Hash> Cache<T, S> {
+    fn new() -> Self {
+        Cache {
+            subsets: Vec::new(),
+            accepting: Vec::new(),
+            found: HashMap::new(),
+            transitions: HashMap::new(),
+            bytes: 0,
+        }
+    }
+
+    fn clear(&mut self) {
+        *self = Cache::new();
+    }
+
+    fn state_bytes(subset: &HashSet<T>) -> usize {
+        size_of::<HashSet<T>>() + subset.len() * size_of::<T>() + size_of::<bool>()
+    }
+
+    fn transition_bytes() -> usize {
+        size_of::<(usize, S, usize)>()
+    }
+
+    fn find(&self, subset: &HashSet<T>) -> Option<usize> {
+        self.found.get(&subset_hash(subset))?.iter()
+            .find(|&&id| self.subsets[id] == *subset)
+            .cloned()
+    }
+
+    fn insert(&mut self, subset: HashSet<T>, accepting: bool) -> usize {
+        let id = self.subsets.len();
+        self.bytes += Self::state_bytes(&subset);
+        self.found.entry(subset_hash(&subset)).or_default().push(id);
+        self.subsets.push(subset);
+        self.accepting.push(accepting);
+        id
+    }
+}
+
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug + Hash, P: Predicate<S>> LazyDFA<T, S, P> {
+    /// Fails on designs with `Look` rules.
+    pub fn new(design: &NFADesign<T, S, P>, options: LazyDFAOptions) -> Result<Self, SubsetError> {
+        if design.has_looks() {
+            return Err(SubsetError::LookRules);
+        }
+        let mut lazy = LazyDFA {
+            start_state: design.start_state(),
+            accept_states: design.accept_state(),
+            rulebook: design.rulebook(),
+            options,
+            cache: Cache::new(),
+            current_state: Current::Cached(0),
+            cache_clears: 0,
+        };
+        lazy.reset();
+        Ok(lazy)
+    }
+
+    /// Go back to the start state, keeping the cache.
+    pub fn reset(&mut self) {
+        let start_state = to_hashset(::std::slice::from_ref(&self.start_state));
+        let start_state = self.rulebook.follow_free_moves(&start_state);
+        self.current_state = match self.current_state {
+            Current::Fallback(_) => Current::Fallback(start_state),
+            Current::Cached(_) => match self.state_for(&start_state) {
+                Some(id) => Current::Cached(id),
+                None => Current::Fallback(start_state),
+            },
+        };
+    }
+
+    pub fn accepting(&self) -> bool {
+        match self.current_state {
+            Current::Cached(id) => self.cache.accepting[id],
+            Current::Fallback(ref states) => !states.is_disjoint(&self.accept_states),
+        }
+    }
+
+    pub fn read_character(&mut self, character: S) {
+        let id = match self.current_state {
+            Current::Cached(id) => id,
+            Current::Fallback(ref states) => {
+                let next_states = self.rulebook.next_states(states, Some(character));
+                self.current_state = Current::Fallback(self.rulebook.follow_free_moves(&next_states));
+                return;
+            },
+        };
+        if let Some(&next) = self.cache.transitions.get(&(id, character)) {
+            self.current_state = Current::Cached(next);
+            return;
+        }
+        let next_states = self.rulebook.next_states(&self.cache.subsets[id], Some(character));
+        let next_states = self.rulebook.follow_free_moves(&next_states);
+        let cache_clears = self.cache_clears;
+        self.current_state = match self.state_for(&next_states) {
+            Some(next) => {
+                // A cleared cache no longer has the state moved from.
+                if self.cache_clears == cache_clears {
+                    self.cache.bytes += Cache::<T, S>::transition_bytes();
+                    self.cache.transitions.insert((id, character), next);
+                }
+                Current::Cached(next)
+            },
+            None => Current::Fallback(next_states),
+        };
+    }
+
+    pub fn read_symbols<I: IntoIterator<Item=S>>(&mut self, symbols: I) {
+        for c in symbols {
+            self.read_character(c);
+        }
+    }
+
+    /// Whether the whole of `symbols` is accepted from the start state.
+    pub fn accept_symbols<I: IntoIterator<Item=S>>(&mut self, symbols: I) -> bool {
+        self.reset();
+        self.read_symbols(symbols);
+        self.accepting()
+    }
+
+    /// How many times the cache was full and cleared.
+    pub fn cache_clears(&self) -> usize { self.cache_clears }
+
+    /// The number of DFA states currently cached.
+    pub fn cached_states(&self) -> usize { self.cache.subsets.len() }
+
+    /// Whether the automaton gave up on caching and simulates the NFA.
+    pub fn is_fallback(&self) -> bool {
+        matches!(self.current_state, Current::Fallback(_))
+    }
+
+    /// The cached state for `subset`, added to the cache if missing. The
+    /// cache is cleared when the new state does not fit, `None` when it was
+    /// cleared too often.
+    fn state_for(&mut self, subset: &HashSet<T>) -> Option<usize> {
+        if let Some(id) = self.cache.find(subset) {
+            return Some(id);
+        }
+        let needed = Cache::<T, S>::state_bytes(subset) + Cache::<T, S>::transition_bytes();
+        if self.cache.bytes + needed > self.options.cache_capacity {
+            if self.cache_clears == self.options.max_cache_clears {
+                self.cache.clear();
+                return None;
+            }
+            self.cache.clear();
+            self.cache_clears += 1;
+        }
+        let accepting = !subset.is_disjoint(&self.accept_states);
+        Some(self.cache.insert(subset.clone(), accepting))
+    }
+}
+
+impl<T: Eq + Clone + Hash> LazyDFA<T> {
+    pub fn read_string(&mut self, s: &str) {
+        self.read_symbols(s.chars());
+    }
+
+    pub fn accept(&mut self, s: &str) -> bool {
+        self.accept_symbols(s.chars())
+    }
+}
@@ -0,0 +1,77 @@
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result;
+
+/// What ends a line for the line anchors and for `.`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum LineTerminator {
+    /// Only `\n`.
+    LF,
+    /// `\n`, `\r` or `\r\n`, where no line boundary falls between the `\r`
+    /// and the `\n` of a `\r\n`.
+    CRLF,
+}
+
+impl LineTerminator {
+    /// The characters that end a line.
+    pub fn characters(self) -> &'static [char] {
+        match self {
+            LineTerminator::LF => &['\n'],
+            LineTerminator::CRLF => &['\n', '\r'],
+        }
+    }
+}
+
+/// A zero-width assertion about the characters around the current
+/// position. Rules asserting a `Look` are only followed where it holds, so
+/// only automata that know the surrounding characters can take them.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Hash)]
+pub enum Look {
+    StartText,
+    EndText,
+    StartLine(LineTerminator),
+    EndLine(LineTerminator),
+}
+
+impl Look {
+    /// Whether the assertion holds between `prev` and `next`, `None` being
+    /// the edge of the input.
+    pub fn holds(self, prev: Option<char>, next: Option<char>) -> bool {
+        match self {
+            Look::StartText => prev.is_none(),
+            Look::EndText => next.is_none(),
+            Look::StartLine(LineTerminator::LF) => prev.map_or(true, |c| c == '\n'),
+            Look::EndLine(LineTerminator::LF) => next.map_or(true, |c| c == '\n'),
+            Look::StartLine(LineTerminator::CRLF) => match prev {
+                None | Some('\n') => true,
+                Some('\r') => next != Some('\n'),
+                Some(_) => false,
+            },
+            Look::EndLine(LineTerminator::CRLF) => match next {
+                None | Some('\r') => true,
+                Some('\n') => prev != Some('\r'),
+                Some(_) => false,
+            },
+        }
+    }
+
+    /// The characters the assertion looks for around the position. All
+    /// other characters are the same to it.
+    pub fn line_characters(self) -> &'static [char] {
+        match self {
+            Look::StartText | Look::EndText => &[],
+            Look::StartLine(terminator) | Look::EndLine(terminator) => terminator.characters(),
+        }
+    }
+}
+
+impl Display for Look {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            Look::StartText => write!(f, "^"),
+            Look::EndText => write!(f, "$"),
+            Look::StartLine(_) => write!(f, "(?m:^)"),
+            Look::EndLine(_) => write!(f, "(?m:$)"),
+        }
+    }
+}
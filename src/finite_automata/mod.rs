@@ -1,11 +1,19 @@
+pub mod classes;
 pub mod faruledata;
+pub mod look;
 pub mod farule;
 pub mod dfarulebook;
 pub mod dfa;
 pub mod dfadesign;
+pub mod densedfa;
+pub mod lazydfa;
 pub mod nfarulebook;
 pub mod nfa;
 pub mod nfadesign;
+pub mod predicate;
+pub mod snapshot;
+pub mod subset;
+pub mod symbol;
 
 #[cfg(test)]
 mod tests {
@@ -14,9 +22,16 @@ mod tests {
     use super::dfarulebook::*;
     use super::dfa::*;
     use super::dfadesign::*;
+    use super::densedfa::*;
+    use super::lazydfa::*;
+    use super::look::*;
     use super::nfarulebook::*;
     use super::nfa::*;
     use super::nfadesign::*;
+    use super::predicate::*;
+    use super::snapshot::*;
+    use super::subset::*;
+    use super::symbol::*;
     use helper::*;
 
     #[test]
@@ -193,4 +208,552 @@ mod tests {
         assert!(!nfa_design.accept("j"));
         assert!(nfa_design.accept("猛"));
     }
+
+    #[test]
+    fn test_nfadesign_match_ends() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&2, 'b', &3),
+                 FARule::new_rulefree(&3, &1)]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[3]), &rulebook);
+        assert_eq!(vec![2, 4, 7], nfa_design.match_ends("ababaab").collect::<Vec<_>>());
+        assert_eq!(vec![2, 4], nfa_design.prefix_match_ends("ababaab", 0).collect::<Vec<_>>());
+        assert!(nfa_design.prefix_match_ends("ba", 0).next().is_none());
+        assert_eq!(vec![4], nfa_design.prefix_match_ends("ababaab", 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_nfa_shortest_longest_prefix() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&2, 'b', &1),
+                 FARule::new_rulechar(&2, 'c', &3)]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[1, 2]), &rulebook);
+        assert_eq!(Some(0), nfa_design.shortest_match("ab"));
+        assert_eq!(Some(3), nfa_design.longest_prefix_match("abaca"));
+        assert_eq!(Some(0), nfa_design.longest_prefix_match("c"));
+
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[3]), &rulebook);
+        assert_eq!(Some(4), nfa_design.shortest_match("abacab"));
+        assert_eq!(None, nfa_design.shortest_match("abb"));
+        assert_eq!(None, nfa_design.longest_prefix_match("aba"));
+
+        let mut nfa = nfa_design.to_nfa();
+        nfa.read_character('a');
+        assert_eq!(Some(1), nfa.longest_prefix_match("cab"));
+    }
+
+    #[test]
+    fn test_nfa_prefix_status() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&2, 'b', &3),
+                 FARule::new_rulechar(&1, 'x', &4), FARule::new_rulechar(&4, 'y', &4)]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[3]), &rulebook);
+        let live_states = nfa_design.live_states();
+        assert!(hashset_eq(&to_hashset(&[1, 2, 3]), &live_states));
+        assert_eq!(PrefixStatus::CouldMatch, nfa_design.prefix_status(""));
+        assert_eq!(PrefixStatus::CouldMatch, nfa_design.prefix_status("a"));
+        assert_eq!(PrefixStatus::Matches, nfa_design.prefix_status("ab"));
+        assert_eq!(PrefixStatus::Dead, nfa_design.prefix_status("abb"));
+        assert_eq!(PrefixStatus::Dead, nfa_design.prefix_status("xy"));
+
+        let mut nfa = nfa_design.to_nfa();
+        nfa.read_character('a');
+        assert_eq!(PrefixStatus::CouldMatch, nfa.prefix_status());
+        assert!(hashset_eq(&live_states, nfa.live_states()));
+    }
+
+    #[test]
+    fn test_faruledata_merge_complement() {
+        let set = vec![FARuleData::range('d', 'f'), FARuleData::char('a'),
+                       FARuleData::range('b', 'c'), FARuleData::char('x')];
+        assert_eq!(vec![FARuleData::range('a', 'f'), FARuleData::char('x')], FARuleData::merge(&set));
+        assert_eq!(vec![FARuleData::range('\0', '`'), FARuleData::range('g', 'w'),
+                        FARuleData::range('y', ::std::char::MAX)],
+                   FARuleData::complement(&set));
+        assert_eq!(vec![FARuleData::range('\0', '\u{D7FF}')],
+                   FARuleData::complement(&[FARuleData::range('\u{E000}', ::std::char::MAX)]));
+    }
+
+    #[test]
+    fn test_nfa_next_characters() {
+        let digits = vec![FARuleData::range('0', '9')];
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&1, 'b', &2),
+                 FARule::new_rulechar(&1, 'c', &5), FARule::new_ruleset(&2, &3, &digits, false),
+                 FARule::new_rulefree(&3, &2), FARule::new_rulechar(&3, ';', &4)]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[4]), &rulebook);
+        let mut nfa = nfa_design.to_nfa();
+        assert_eq!(vec![FARuleData::range('a', 'b')], nfa.next_characters());
+        nfa.read_character('a');
+        assert_eq!(digits, nfa.next_characters());
+        nfa.read_character('7');
+        assert_eq!(vec![FARuleData::range('0', '9'), FARuleData::char(';')],
+                   nfa.next_characters());
+    }
+
+    #[test]
+    fn test_nfa_completions() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&1, 'b', &2),
+                 FARule::new_rulechar(&2, 'c', &3), FARule::new_rulechar(&1, 'x', &3),
+                 FARule::new_rulechar(&3, 'z', &3)]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[3]), &rulebook);
+        let nfa = nfa_design.to_nfa();
+        assert_eq!(vec![vec![vec![FARuleData::char('x')]],
+                        vec![vec![FARuleData::range('a', 'b')], vec![FARuleData::char('c')]],
+                        vec![vec![FARuleData::char('x')], vec![FARuleData::char('z')]]],
+                   nfa.completions(3));
+        let mut nfa = nfa_design.to_nfa();
+        nfa.read_character('x');
+        assert_eq!(vec![Vec::<Vec<FARuleData>>::new()], nfa.completions(1));
+    }
+
+    #[test]
+    fn test_dfa_snapshot() {
+        let rulebook = DFARulebook::new(
+            vec![
+                FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&1, 'b', &1),
+                FARule::new_rulechar(&2, 'a', &2), FARule::new_rulechar(&2, 'b', &3),
+                FARule::new_rulechar(&3, 'a', &3), FARule::new_rulechar(&3, 'b', &3)
+            ]);
+        let dfa_design = DFADesign::new(1, &vec![3], &rulebook);
+        let ids = dfa_design.state_ids();
+        let mut dfa = dfa_design.to_dfa();
+        dfa.read_string("ba");
+        let snapshot = dfa.snapshot(&ids).unwrap();
+        assert_eq!(vec![1], snapshot.states);
+
+        let mut restored = dfa_design.to_dfa();
+        restored.restore(&ids, &snapshot.to_string().parse().unwrap()).unwrap();
+        assert!(!restored.accepting());
+        restored.read_character('b');
+        assert!(restored.accepting());
+    }
+
+    #[test]
+    fn test_nfa_snapshot() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &1), FARule::new_rulechar(&1, 'b', &1),
+                 FARule::new_rulechar(&1, 'b', &2), FARule::new_rulechar(&2, 'a', &3),
+                 FARule::new_rulechar(&2, 'b', &3), FARule::new_rulechar(&3, 'a', &4),
+                 FARule::new_rulechar(&3, 'b', &4)
+            ]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[4]), &rulebook);
+        let ids = nfa_design.state_ids();
+        assert_eq!(4, ids.len());
+        assert_eq!(Some(&3), ids.state(2));
+
+        let mut nfa = nfa_design.to_nfa();
+        nfa.read_string("ba");
+        let snapshot = nfa.snapshot(&ids).unwrap();
+        assert_eq!(vec![0, 2], snapshot.states);
+
+        let mut restored = nfa_design.to_nfa();
+        restored.restore(&ids, &snapshot).unwrap();
+        restored.read_character('b');
+        assert!(restored.accepting());
+
+        let other = NFADesign::new(&1, &to_hashset(&[3]), &rulebook).state_ids();
+        assert_eq!(Err(SnapshotError::OtherAutomaton), restored.restore(&other, &snapshot));
+        let unknown = Snapshot { fingerprint: ids.fingerprint(), states: vec![7], prev: None };
+        assert_eq!(Err(SnapshotError::UnknownState(7)), restored.restore(&ids, &unknown));
+        assert_eq!(Err(SnapshotError::Malformed), "12:x".parse::<Snapshot>());
+        let foreign = nfa_design.to_nfa_with_state(&to_hashset(&[9]));
+        assert_eq!(Err(SnapshotError::ForeignState), foreign.snapshot(&ids));
+
+        // Fingerprints are part of saved snapshots and must not change.
+        assert_eq!(1315473098140230119, ids.fingerprint());
+    }
+
+    #[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+    enum Token { Number, Plus, Times }
+
+    impl Symbol for Token {
+        fn min_symbol() -> Self { Token::Number }
+        fn max_symbol() -> Self { Token::Times }
+        fn successor(self) -> Option<Self> {
+            match self {
+                Token::Number => Some(Token::Plus),
+                Token::Plus => Some(Token::Times),
+                Token::Times => None,
+            }
+        }
+        fn predecessor(self) -> Option<Self> {
+            match self {
+                Token::Number => None,
+                Token::Plus => Some(Token::Number),
+                Token::Times => Some(Token::Plus),
+            }
+        }
+    }
+
+    #[test]
+    fn test_symbol_alphabets() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 0x1bu8, &2),
+                 FARule::new_ruleset(&2, &2, &vec![FARuleData::range(b'0', b'9')], false),
+                 FARule::new_rulechar(&2, b'm', &3)
+            ]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[3]), &rulebook);
+        assert!(nfa_design.accept_symbols(b"\x1b[31m".iter().cloned().filter(|&b| b != b'[')));
+        assert!(!nfa_design.accept_symbols(vec![0x1b, b'm', b'm']));
+        assert_eq!(vec![FARuleData::range(0, 0x1a), FARuleData::range(0x1c, 0xff)],
+                   FARuleData::complement(&[FARuleData::char(0x1bu8)]));
+
+        let rulebook = DFARulebook::new(
+            vec![FARule::new_rulechar(&1, Token::Number, &2),
+                 FARule::new_ruleset(&2, &1, &vec![FARuleData::range(Token::Plus, Token::Times)], false)
+            ]);
+        let dfa_design = DFADesign::new(1, &vec![2], &rulebook);
+        assert!(dfa_design.accept_symbols(vec![Token::Number, Token::Times, Token::Number]));
+        assert!(!dfa_design.accept_symbols(vec![Token::Number, Token::Plus]));
+
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, Token::Number, &2), FARule::new_ruleany(&2, &1)]);
+        let nfa = NFADesign::new(&1, &to_hashset(&[2]), &rulebook).to_nfa();
+        assert_eq!(vec![vec![vec![FARuleData::char(Token::Number)]],
+                        vec![vec![FARuleData::char(Token::Number)],
+                             vec![FARuleData::range(Token::Number, Token::Times)],
+                             vec![FARuleData::char(Token::Number)]]],
+                   nfa.completions(2));
+        let mut nfa = nfa;
+        nfa.read_character(Token::Number);
+        assert_eq!(vec![FARuleData::range(Token::Number, Token::Times)], nfa.next_characters());
+    }
+
+    #[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+    enum Level { Debug, Info, Warn, Error }
+
+    const LEVELS: [Level; 4] = [Level::Debug, Level::Info, Level::Warn, Level::Error];
+
+    /// The levels a guard accepts, one bit per level.
+    #[derive(Debug,Clone,Copy,PartialEq)]
+    struct Levels(u8);
+
+    impl Levels {
+        fn at_least(level: Level) -> Self {
+            Levels(LEVELS.iter().filter(|&&l| l >= level).fold(0, |mask, &l| mask | 1 << l as u8))
+        }
+    }
+
+    impl Predicate<Level> for Levels {
+        fn top() -> Self { Levels(0b1111) }
+        fn from_ranges(ranges: &[FARuleData<Level>]) -> Self {
+            Levels(LEVELS.iter().filter(|l| ranges.iter().any(|data| data.applies_to(l)))
+                         .fold(0, |mask, &l| mask | 1 << l as u8))
+        }
+        fn accepts(&self, level: &Level) -> bool { self.0 & 1 << *level as u8 != 0 }
+        fn and(&self, other: &Self) -> Self { Levels(self.0 & other.0) }
+        fn or(&self, other: &Self) -> Self { Levels(self.0 | other.0) }
+        fn not(&self) -> Self { Levels(!self.0 & 0b1111) }
+        fn is_satisfiable(&self) -> bool { self.0 != 0 }
+    }
+
+    #[test]
+    fn test_predicate_rules() {
+        // Two warnings or worse, with anything but errors in between.
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulepredicate(&1, Levels::top(), &1),
+                 FARule::new_rulepredicate(&1, Levels::at_least(Level::Warn), &2),
+                 FARule::new_rulepredicate(&2, Levels::at_least(Level::Error).not(), &2),
+                 FARule::new_ruleset(&2, &3, &vec![FARuleData::range(Level::Warn, Level::Error)], false)
+                     .into_guarded()
+            ]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[3]), &rulebook);
+        let dfa_design = nfa_design.determinize().unwrap();
+        let inputs = vec![
+            (vec![Level::Warn, Level::Error], true),
+            (vec![Level::Debug, Level::Error, Level::Info, Level::Warn], true),
+            (vec![Level::Warn, Level::Info], false),
+            (vec![Level::Error, Level::Debug, Level::Info], false),
+            (vec![], false),
+        ];
+        for (input, accepted) in inputs {
+            assert_eq!(accepted, nfa_design.accept_symbols(input.clone()));
+            assert_eq!(accepted, dfa_design.accept_symbols(input));
+        }
+        for (guard, states) in rulebook.guarded_moves(&to_hashset(&[1])) {
+            let expected = if guard.accepts(&Level::Warn) { to_hashset(&[1, 2]) } else { to_hashset(&[1]) };
+            assert_eq!(expected, states);
+        }
+        assert_eq!(Levels(0), Levels::bottom());
+    }
+
+    #[test]
+    fn test_determinize_ranges() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulefree(&1, &3),
+                 FARule::new_ruleset(&2, &2, &vec![FARuleData::range('a', 'z')], false),
+                 FARule::new_ruleany(&3, &3), FARule::new_rulechar(&3, '!', &4),
+                 FARule::new_rulepredicate(&2, RangeSet::new(&[FARuleData::char('!')]), &4)
+            ]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[4]), &rulebook);
+        let dfa_design = nfa_design.determinize().unwrap();
+        for s in &["a!", "abc!", "!", "x!!", "a", "", "ab!c", "héllo!"] {
+            assert_eq!(nfa_design.accept(s), dfa_design.accept_symbols(s.chars()));
+        }
+        let set = RangeSet::new(&[FARuleData::range('a', 'f')]);
+        assert_eq!(&[FARuleData::range('c', 'f')][..],
+                   set.and(&RangeSet::new(&[FARuleData::range('c', 'z')])).ranges());
+        assert!(!set.and(&set.not()).is_satisfiable());
+        assert_eq!(RangeSet::top(), set.or(&set.not()));
+    }
+
+    #[test]
+    fn test_rulebook_transitions() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulefree(&1, &3),
+                 FARule::new_rulechar(&2, 'b', &1), FARule::new_ruletag(&1, 0, &4),
+                 FARule::new_ruleany(&1, &4), FARule::new_rulelook(&3, Look::StartText, &4)
+            ]);
+        let describe = |rules: Vec<&FARule<u32>>| {
+            rules.iter().map(|rule| rule.to_string()).collect::<Vec<String>>()
+        };
+        assert_eq!(vec!["FARule 1 --a--> 2", "FARule 1 --free--> 3",
+                        "FARule 1 --tag 0--> 4", "FARule 1 --any--> 4"],
+                   describe(rulebook.rules_from(&1)));
+        assert_eq!(vec!["FARule 1 --a--> 2", "FARule 1 --any--> 4"],
+                   describe(rulebook.consuming_rules_from(&1)));
+        assert_eq!(vec!["FARule 3 --look ^--> 4"], describe(rulebook.free_rules_from(&3)));
+        assert!(rulebook.rules_from(&4).is_empty());
+        assert_eq!(vec![2, 4], rulebook.follow_rules_for(&1, Some('a')));
+        assert_eq!(vec![3, 4], rulebook.follow_rules_for(&1, None));
+        assert!(hashset_eq(&to_hashset(&[1, 2]), &rulebook.states_reaching(&to_hashset(&[2]))));
+
+        let rulebook = DFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&2, 'a', &1),
+                 FARule::new_ruleany(&1, &1)]);
+        assert_eq!(2, rulebook.next_state(&1, 'a'));
+        assert_eq!(1, rulebook.next_state(&1, 'b'));
+        assert!(rulebook.rule_for(&2, 'b').is_none());
+        assert!(rulebook.rule_for(&3, 'a').is_none());
+    }
+
+    #[test]
+    fn test_nfa_look_symbols() {
+        // Any byte, then b at the start of a line.
+        let design = |terminator| NFADesign::new(&1, &to_hashset(&[4]), &NFARulebook::new(
+            vec![FARule::new_ruleany(&1, &2), FARule::new_rulelook(&2, Look::StartLine(terminator), &3),
+                 FARule::new_rulechar(&3, b'b', &4)]));
+        let lf = design(LineTerminator::LF);
+        assert!(lf.accept_symbols(b"\nb".iter().cloned()));
+        assert!(!lf.accept_symbols(b"\rb".iter().cloned()));
+        assert!(!lf.accept_symbols(b"xb".iter().cloned()));
+        assert_eq!(vec![b'\n'], lf.rulebook().line_symbols());
+        let crlf = design(LineTerminator::CRLF);
+        assert!(crlf.accept_symbols(b"\rb".iter().cloned()));
+        assert_eq!(vec![b'\n', b'\r'], crlf.rulebook().line_symbols());
+        let completions = lf.to_nfa().completions(2);
+        assert_eq!(vec![vec![vec![FARuleData::char(b'\n')], vec![FARuleData::char(b'b')]]], completions);
+
+        // Symbols without characters never end a line.
+        let tokens = NFADesign::new(&1, &to_hashset(&[3]), &NFARulebook::new(
+            vec![FARule::new_ruleany(&1, &2), FARule::new_rulelook(&2, Look::StartLine(LineTerminator::LF), &3)]));
+        assert!(tokens.rulebook().line_symbols().is_empty());
+        assert!(!tokens.accept_symbols(vec![Token::Number]));
+    }
+
+    #[test]
+    fn test_nfa_long_free_chain() {
+        let length = 100_000;
+        let mut rules = (0..length).map(|i| FARule::new_rulefree(&i, &(i + 1))).collect::<Vec<FARule<u32>>>();
+        rules.push(FARule::new_rulechar(&length, 'a', &0));
+        let rulebook = NFARulebook::new(rules);
+        assert_eq!(length as usize + 1, rulebook.follow_free_moves(&to_hashset(&[0])).len());
+
+        let nfa_design = NFADesign::new(&0, &to_hashset(&[length]), &rulebook);
+        let mut nfa = nfa_design.to_nfa();
+        assert!(nfa.accepting());
+        nfa.read_string("aaa");
+        assert!(nfa.accepting());
+        assert_eq!(length as usize + 1, nfa.current_state().len());
+        nfa.read_character('b');
+        assert!(!nfa.accepting());
+    }
+
+    #[test]
+    fn test_nfa_completions_free_moves() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulefree(&2, &3),
+                 FARule::new_rulechar(&3, 'b', &4)]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[4]), &rulebook);
+        let completions = nfa_design.to_nfa().completions(1);
+        assert_eq!(vec![vec![vec![FARuleData::char('a')], vec![FARuleData::char('b')]]], completions);
+
+        // Twenty copies of a|b|c|d, where every copy has four branches.
+        let rules = (0..20).flat_map(|copy| {
+            "abcd".chars().enumerate().flat_map(move |(i, c)| {
+                let branch = 5 * copy + i as u32 + 1;
+                vec![FARule::new_rulechar(&(5 * copy), c, &branch), FARule::new_rulefree(&branch, &(5 * copy + 5))]
+            })
+        }).collect();
+        let nfa_design = NFADesign::new(&0, &to_hashset(&[100]), &NFARulebook::new(rules));
+        let completions = nfa_design.to_nfa().completions(3);
+        assert_eq!(3, completions.len());
+        assert!(completions.iter().all(|completion| completion.len() == 20));
+        assert_eq!(vec![vec![FARuleData::char('a')]; 20], completions[0]);
+    }
+
+    #[test]
+    fn test_nfa_to_dfa_design() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &1), FARule::new_rulechar(&1, 'b', &1),
+                 FARule::new_rulechar(&1, 'b', &2), FARule::new_rulefree(&2, &3),
+                 FARule::new_rulechar(&3, 'a', &4), FARule::new_rulechar(&3, 'b', &4)
+            ]);
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[4]), &rulebook);
+        let dfa = nfa_design.to_dfa_design().unwrap();
+        assert_eq!(5, dfa.len());
+        assert!(hashset_eq(&to_hashset(&[1]), dfa.nfa_states(0).unwrap()));
+        let after_b = dfa.design().rulebook().next_state(&0, 'b');
+        assert!(hashset_eq(&to_hashset(&[1, 2, 3]), dfa.nfa_states(after_b).unwrap()));
+        assert!(dfa.nfa_states(5).is_none());
+        for s in &["ba", "bb", "abab", "", "b", "aa", "bbaa", "bc"] {
+            assert_eq!(nfa_design.accept(s), dfa.design().accept(s));
+        }
+
+        assert_eq!(Err(SubsetError::TooManyStates(2)),
+                   nfa_design.to_dfa_design_with_limit(2).map(|dfa| dfa.len()));
+        assert_eq!(Err(SubsetError::TooManyStates(0)),
+                   nfa_design.to_dfa_design_with_limit(0).map(|dfa| dfa.len()));
+        assert_eq!(Ok(5), nfa_design.to_dfa_design_with_limit(5).map(|dfa| dfa.len()));
+        assert_eq!("DFA exceeds the limit of 2 states", SubsetError::TooManyStates(2).to_string());
+    }
+
+    #[test]
+    fn test_dfa_minimize() {
+        // (a|b)*abb with the states 1 and 3 equivalent and 6 unreachable.
+        let rulebook = DFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&1, 'b', &3),
+                 FARule::new_rulechar(&2, 'a', &2), FARule::new_rulechar(&2, 'b', &4),
+                 FARule::new_rulechar(&3, 'a', &2), FARule::new_rulechar(&3, 'b', &3),
+                 FARule::new_rulechar(&4, 'a', &2), FARule::new_rulechar(&4, 'b', &5),
+                 FARule::new_rulechar(&5, 'a', &2), FARule::new_rulechar(&5, 'b', &3),
+                 FARule::new_rulechar(&6, 'a', &5), FARule::new_rulechar(&6, 'b', &6)
+            ]);
+        let dfa_design = DFADesign::new(1, &vec![5], &rulebook);
+        let minimal = dfa_design.minimize();
+        assert_eq!(4, minimal.state_ids().len());
+        assert_eq!(vec![3], minimal.accept_states());
+        let mut inputs = vec![String::new()];
+        for _ in 0..5 {
+            inputs = inputs.iter().flat_map(|s| vec![format!("{}a", s), format!("{}b", s)]).collect();
+            for s in inputs.iter() {
+                assert_eq!(dfa_design.accept(s), minimal.accept(s));
+            }
+        }
+        assert_eq!(4, minimal.minimize().state_ids().len());
+
+        // Dead states merge, and symbols without a rule keep having none.
+        let nfa_design = NFADesign::new(&1, &to_hashset(&[3]), &NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&1, 'a', &4),
+                 FARule::new_rulechar(&2, 'b', &3), FARule::new_rulechar(&4, 'b', &3)]));
+        let minimal = nfa_design.determinize().unwrap().minimize();
+        assert_eq!(4, minimal.state_ids().len());
+        for s in &["ab", "a", "b", "abb", "xab", ""] {
+            assert_eq!(nfa_design.accept(s), minimal.accept(s));
+        }
+        let partial = DFADesign::new(1, &vec![2], &DFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulechar(&2, 'a', &2)])).minimize();
+        assert_eq!(1, partial.rulebook().rules_from(&0).len());
+        assert!(partial.rulebook().rule_for(&0, 'b').is_none());
+    }
+
+    #[test]
+    fn test_lazy_dfa() {
+        // (a|b)*a(a|b)(a|b)(a|b), whose DFA has 16 states.
+        let mut rules = vec![FARule::new_rulechar(&0, 'a', &0), FARule::new_rulechar(&0, 'b', &0),
+                             FARule::new_rulechar(&0, 'a', &1)];
+        for i in 1..4 {
+            rules.push(FARule::new_rulechar(&i, 'a', &(i + 1)));
+            rules.push(FARule::new_rulechar(&i, 'b', &(i + 1)));
+        }
+        let nfa_design = NFADesign::new(&0, &to_hashset(&[4]), &NFARulebook::new(rules));
+        let mut inputs = vec![String::new()];
+        for _ in 0..7 {
+            inputs = inputs.iter().flat_map(|s| vec![format!("{}a", s), format!("{}b", s)]).collect();
+        }
+
+        let mut lazy = nfa_design.to_lazy_dfa(LazyDFAOptions::default()).unwrap();
+        for s in inputs.iter() {
+            assert_eq!(nfa_design.accept(s), lazy.accept(s));
+        }
+        assert_eq!(16, lazy.cached_states());
+        assert_eq!(0, lazy.cache_clears());
+        assert!(!lazy.is_fallback());
+
+        let options = LazyDFAOptions { cache_capacity: 1024, max_cache_clears: 1000 };
+        let mut lazy = nfa_design.to_lazy_dfa(options).unwrap();
+        for s in inputs.iter() {
+            assert_eq!(nfa_design.accept(s), lazy.accept(s));
+        }
+        assert!(lazy.cache_clears() > 0);
+        assert!(!lazy.is_fallback());
+
+        let options = LazyDFAOptions { cache_capacity: 1024, max_cache_clears: 2 };
+        let mut lazy = nfa_design.to_lazy_dfa(options).unwrap();
+        for s in inputs.iter() {
+            assert_eq!(nfa_design.accept(s), lazy.accept(s));
+        }
+        assert_eq!(2, lazy.cache_clears());
+        assert!(lazy.is_fallback());
+        lazy.reset();
+        lazy.read_string("abbb");
+        assert!(lazy.accepting());
+    }
+
+    #[test]
+    fn test_symbol_classes() {
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&1, 'a', &2), FARule::new_rulefree(&1, &3),
+                 FARule::new_ruleset(&2, &2, &vec![FARuleData::range('a', 'z')], false),
+                 FARule::new_ruleset(&2, &4, &vec![FARuleData::range('0', '9'), FARuleData::char('x')], true),
+                 FARule::new_ruleany(&3, &4)]);
+        let classes = NFADesign::new(&1, &to_hashset(&[4]), &rulebook).symbol_classes();
+        // Not [a-z] nor a digit, a digit, a, [b-w] or [yz], x.
+        assert_eq!(5, classes.len());
+        assert_eq!(classes.class_of('!'), classes.class_of('{'));
+        assert_eq!(classes.class_of('!'), classes.class_of(char::MAX));
+        assert_eq!(classes.class_of('b'), classes.class_of('z'));
+        assert_ne!(classes.class_of('a'), classes.class_of('b'));
+        assert_ne!(classes.class_of('x'), classes.class_of('y'));
+        assert_eq!(0, classes.class_of('\0'));
+        assert_eq!('0', classes.representative(classes.class_of('5')));
+        assert_eq!(vec![FARuleData::range('b', 'w'), FARuleData::range('y', 'z')],
+                   classes.ranges(classes.class_of('y')));
+
+        let bytes = NFARulebook::new(vec![FARule::new_rulechar(&1, 7u8, &2)]);
+        let classes = NFADesign::new(&1, &to_hashset(&[2]), &bytes).symbol_classes();
+        assert_eq!(2, classes.len());
+        assert_eq!(classes.class_of(0), classes.class_of(255));
+    }
+
+    #[test]
+    fn test_dense_dfa() {
+        // (a|b)*a(a|b)[0-9]+
+        let rulebook = NFARulebook::new(
+            vec![FARule::new_rulechar(&0, 'a', &0), FARule::new_rulechar(&0, 'b', &0),
+                 FARule::new_rulechar(&0, 'a', &1), FARule::new_ruleset(&1, &2, &vec![FARuleData::range('a', 'b')], false),
+                 FARule::new_ruleset(&2, &3, &vec![FARuleData::range('0', '9')], false), FARule::new_rulefree(&3, &2)]);
+        let nfa_design = NFADesign::new(&0, &to_hashset(&[3]), &rulebook);
+        let dfa = nfa_design.to_dense_dfa(true).unwrap();
+        for s in &["ab1", "bbaa42", "ab", "a1", "ab1x", "", "abab7"] {
+            assert_eq!(nfa_design.accept(s), dfa.accept(s));
+        }
+        assert!(!dfa.is_accepting(DEAD));
+        assert_eq!(DEAD, dfa.next_state(dfa.start_state(), 'x'));
+        assert_eq!(DEAD, dfa.next_state(DEAD, 'a'));
+        assert!(dfa.is_match("ab1x"));
+        assert!(!dfa.is_match("xab1"));
+
+        let unanchored = nfa_design.to_dense_dfa(false).unwrap();
+        assert!(unanchored.is_match("xxab1"));
+        assert!(unanchored.is_match("--aa0--"));
+        assert!(!unanchored.is_match("ab-1 ba"));
+        assert!("ab1x-".chars().all(|c| unanchored.next_state(DEAD, c) == DEAD));
+        assert!((1..unanchored.len() as u32).all(|state| {
+            "ab1x-".chars().all(|c| unanchored.next_state(state, c) != DEAD)
+        }));
+        assert_eq!(Err(SubsetError::TooManyStates(3)),
+                   DenseDFA::new(&nfa_design, true, 3).map(|dfa| dfa.len()));
+    }
 }
@@ -1,38 +1,289 @@
-use std::collections::HashSet;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
 use std::hash::Hash;
+use std::sync::{Arc, OnceLock};
 
+use super::faruledata::{FARuleData};
 use super::nfarulebook::{NFARulebook};
+use super::predicate::{Predicate, RangeSet};
+use super::snapshot::{Snapshot, SnapshotError, StateIds};
+use super::subset::{subset_hash};
+use super::symbol::{Symbol};
+
+/// How a prefix of the input relates to the language of an automaton.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum PrefixStatus {
+    /// The input read so far is accepted.
+    Matches,
+    /// The input is not accepted, but some continuation of it would be.
+    CouldMatch,
+    /// No continuation of the input can be accepted.
+    Dead,
+}
 
 #[derive(Clone)]
-pub struct NFA<T> {
+pub struct NFA<T, S = char, P = RangeSet<S>> {
+    /// Kept closed under free moves. `Look` rules are only taken once the
+    /// symbol after them is known.
     current_state: HashSet<T>,
+    /// What the `Look` rules see of the symbol read last, see
+    /// `NFARulebook::look_char`. `None` at the start of the input.
+    prev: Option<char>,
     pub accept_states: HashSet<T>,
-    pub rulebook: NFARulebook<T>,
+    pub rulebook: NFARulebook<T, S, P>,
+    /// The states that can still reach an accept state, found on first use
+    /// and shared with the design the automaton came from.
+    live_states: Arc<OnceLock<HashSet<T>>>,
 }
 
-impl<T: Eq + Clone + Hash> NFA<T> {
-    pub fn new(current_state: &HashSet<T>, accept_states: &HashSet<T>, rulebook: &NFARulebook<T>) -> Self {
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug, P: Predicate<S>> NFA<T, S, P> {
+    pub fn new(current_state: &HashSet<T>, accept_states: &HashSet<T>, rulebook: &NFARulebook<T, S, P>) -> Self {
+        NFA::with_live_states(current_state, accept_states, rulebook, &Arc::new(OnceLock::new()))
+    }
+
+    pub(crate) fn with_live_states(current_state: &HashSet<T>,
+                                   accept_states: &HashSet<T>,
+                                   rulebook: &NFARulebook<T, S, P>,
+                                   live_states: &Arc<OnceLock<HashSet<T>>>) -> Self {
         NFA{
-            current_state: current_state.clone(),
+            current_state: rulebook.follow_free_moves(current_state),
+            prev: None,
             accept_states: accept_states.clone(),
-            rulebook: rulebook.clone()}
+            rulebook: rulebook.clone(),
+            live_states: live_states.clone()}
     }
 
     pub fn current_state(&self) -> HashSet<T> {
-        self.rulebook.follow_free_moves(&self.current_state)
+        self.current_state.clone()
     }
 
+    /// Whether the input read so far is accepted if it ends here.
     pub fn accepting(&self) -> bool {
-        !self.current_state().is_disjoint(&self.accept_states)
+        self.accepting_before(None)
     }
 
-    pub fn read_character(&mut self, character: char) {
-        self.current_state = self.rulebook.next_states(&self.current_state(), Some(character));
+    /// Whether the input read so far is accepted if `next` comes after it,
+    /// `None` being the end of the input.
+    fn accepting_before(&self, next: Option<S>) -> bool {
+        !self.states_before(next).is_disjoint(&self.accept_states)
     }
 
-    pub fn read_string(&mut self, s: &str) {
-        for c in s.chars() {
+    /// The current states with those reached by the `Look` rules that hold
+    /// before `next`.
+    fn states_before(&self, next: Option<S>) -> Cow<'_, HashSet<T>> {
+        if self.rulebook.has_looks() {
+            Cow::Owned(self.rulebook.follow_free_moves_around(&self.current_state, self.prev, next))
+        } else {
+            Cow::Borrowed(&self.current_state)
+        }
+    }
+
+    pub fn read_character(&mut self, character: S) {
+        let next_states = self.rulebook.next_states(&self.states_before(Some(character)), Some(character));
+        self.current_state = self.rulebook.follow_free_moves(&next_states);
+        self.prev = Some(self.rulebook.look_char(character));
+    }
+
+    pub fn read_symbols<I: IntoIterator<Item=S>>(&mut self, symbols: I) {
+        for c in symbols {
             self.read_character(c);
         }
     }
+
+    pub fn snapshot(&self, ids: &StateIds<T, S, P>) -> Result<Snapshot, SnapshotError> {
+        let mut snapshot = ids.snapshot(&self.current_state)?;
+        if self.rulebook.has_looks() {
+            snapshot.prev = self.prev;
+        }
+        Ok(snapshot)
+    }
+
+    /// Continue from the states recorded in `snapshot`, after the symbol
+    /// it was taken after.
+    pub fn restore(&mut self, ids: &StateIds<T, S, P>, snapshot: &Snapshot) -> Result<(), SnapshotError> {
+        let states = ids.restore(snapshot)?.into_iter().collect();
+        self.current_state = self.rulebook.follow_free_moves(&states);
+        self.prev = snapshot.prev;
+        Ok(())
+    }
+
+    /// The states from which an accept state is still reachable.
+    pub fn live_states(&self) -> &HashSet<T> {
+        self.live_states.get_or_init(|| self.rulebook.states_reaching(&self.accept_states))
+    }
+
+    /// Classify the input read so far.
+    pub fn prefix_status(&self) -> PrefixStatus {
+        if self.accepting() {
+            PrefixStatus::Matches
+        } else if !self.current_state.is_disjoint(self.live_states()) {
+            PrefixStatus::CouldMatch
+        } else {
+            PrefixStatus::Dead
+        }
+    }
+}
+
+impl<T: Eq + Clone + Hash, S: Symbol> NFA<T, S> {
+    /// The characters that can be read next without leaving the live
+    /// states, as merged ranges.
+    pub fn next_characters(&self) -> Vec<FARuleData<S>> {
+        let live_states = self.live_states();
+        let ranges = if self.rulebook.has_looks() {
+            self.step_classes(&self.current_state, self.prev, live_states).into_iter()
+                .flat_map(|(class, _, _)| class)
+                .collect::<Vec<FARuleData<S>>>()
+        } else {
+            self.current_state.iter()
+                .flat_map(|state| self.rulebook.rules_from(state))
+                .filter(|rule| live_states.contains(&rule.next_state))
+                .flat_map(|rule| rule.ranges())
+                .collect::<Vec<FARuleData<S>>>()
+        };
+        FARuleData::merge(&ranges)
+    }
+
+    /// Up to `limit` of the shortest inputs that take the automaton from its
+    /// current state to an accept state, shortest first. Each completion is
+    /// a sequence of character classes, any character of a class can be
+    /// chosen at that step.
+    pub fn completions(&self, limit: usize) -> Vec<Vec<Vec<FARuleData<S>>>> {
+        let live_states = self.live_states();
+        let mut completions = Vec::new();
+        let mut frontier = vec![(Vec::new(), self.current_state(), self.prev)];
+        while !frontier.is_empty() && completions.len() < limit {
+            let mut next_frontier: Vec<(_, HashSet<T>, Option<char>)> = Vec::new();
+            // Inputs of one length reaching the same states have the same
+            // continuations, the first `limit` of them are all that can be
+            // reported.
+            let mut reached: HashMap<u64, Vec<(usize, usize)>> = HashMap::new();
+            for (completion, states, prev) in frontier {
+                let end_states = self.rulebook.follow_free_moves_around(&states, prev, None);
+                if !end_states.is_disjoint(&self.accept_states) {
+                    if completions.len() == limit {
+                        break;
+                    }
+                    completions.push(completion.clone());
+                }
+                for (class, next_states, look_char) in self.step_classes(&states, prev, live_states) {
+                    let candidates = reached.entry(subset_hash(&next_states)).or_default();
+                    let same = candidates.iter_mut().find(|&&mut (i, _)| {
+                        next_frontier[i].1 == next_states && next_frontier[i].2 == Some(look_char)
+                    });
+                    match same {
+                        Some(&mut (_, ref mut count)) if *count >= limit => continue,
+                        Some(&mut (_, ref mut count)) => *count += 1,
+                        None => candidates.push((next_frontier.len(), 1)),
+                    }
+                    let mut completion = completion.clone();
+                    completion.push(class);
+                    next_frontier.push((completion, next_states, Some(look_char)));
+                }
+            }
+            frontier = next_frontier;
+        }
+        completions
+    }
+
+    /// Split the symbols readable from `states` after `prev` into classes
+    /// that lead to the same live states and look the same to the `Look`
+    /// rules, with what they see of the class.
+    fn step_classes(&self, states: &HashSet<T>, prev: Option<char>, live_states: &HashSet<T>)
+                    -> Vec<(Vec<FARuleData<S>>, HashSet<T>, char)> {
+        // Every range [start, end] contributes the boundaries start and the
+        // symbol after end, `None` being past the end of the alphabet.
+        let mut boundaries = Vec::new();
+        let reachable = if self.rulebook.has_looks() {
+            for symbol in self.rulebook.line_symbols() {
+                boundaries.push(Some(symbol));
+                boundaries.push(symbol.successor());
+            }
+            // The `Look` rules that hold before some symbol.
+            Cow::Owned(self.rulebook.follow_free_moves_around(states, prev, None))
+        } else {
+            Cow::Borrowed(states)
+        };
+        for rule in reachable.iter().flat_map(|state| self.rulebook.rules_from(state)) {
+            for data in rule.ranges() {
+                let (start, end) = data.bounds();
+                boundaries.push(Some(start));
+                boundaries.push(end.successor());
+            }
+        }
+        boundaries.sort_by_key(|bound| (bound.is_none(), *bound));
+        boundaries.dedup();
+
+        let mut classes: Vec<(Vec<FARuleData<S>>, HashSet<T>, char)> = Vec::new();
+        for window in boundaries.windows(2) {
+            let start = window[0].unwrap();
+            let end = match window[1] {
+                Some(c) => c.predecessor().unwrap(),
+                None => S::max_symbol(),
+            };
+            let before = if self.rulebook.has_looks() {
+                Cow::Owned(self.rulebook.follow_free_moves_around(states, prev, Some(start)))
+            } else {
+                Cow::Borrowed(states)
+            };
+            let next_states = self.rulebook.next_states(&before, Some(start));
+            let next_states = self.rulebook.follow_free_moves(&next_states)
+                .intersection(live_states).cloned().collect::<HashSet<T>>();
+            if next_states.is_empty() {
+                continue;
+            }
+            let data = FARuleData::merge(&[FARuleData::range(start, end)]);
+            let look_char = self.rulebook.look_char(start);
+            match classes.iter_mut().find(|class| class.1 == next_states && class.2 == look_char) {
+                Some(class) => class.0.extend(data),
+                None => classes.push((data, next_states, look_char)),
+            }
+        }
+        classes.into_iter().map(|(data, next_states, look_char)| (FARuleData::merge(&data), next_states, look_char)).collect()
+    }
+}
+
+impl<T: Eq + Clone + Hash> NFA<T> {
+    pub fn read_string(&mut self, s: &str) {
+        self.read_symbols(s.chars());
+    }
+
+    /// Read `s` until an accept state is reached, returning the byte length
+    /// of the shortest accepted prefix. `Look` rules see the rest of `s`.
+    pub fn shortest_match(&mut self, s: &str) -> Option<usize> {
+        let mut chars = s.char_indices().peekable();
+        loop {
+            let (pos, next) = chars.peek().map_or((s.len(), None), |&(i, c)| (i, Some(c)));
+            if self.accepting_before(next) {
+                return Some(pos);
+            }
+            match chars.next() {
+                Some((_, c)) => self.read_character(c),
+                None => return None,
+            }
+            if self.current_state.is_empty() {
+                return None;
+            }
+        }
+    }
+
+    /// Read `s` until no state is left, returning the byte length of the
+    /// longest accepted prefix. `Look` rules see the rest of `s`.
+    pub fn longest_prefix_match(&mut self, s: &str) -> Option<usize> {
+        let mut longest = None;
+        let mut chars = s.char_indices().peekable();
+        loop {
+            let (pos, next) = chars.peek().map_or((s.len(), None), |&(i, c)| (i, Some(c)));
+            if self.accepting_before(next) {
+                longest = Some(pos);
+            }
+            match chars.next() {
+                Some((_, c)) => self.read_character(c),
+                None => return longest,
+            }
+            if self.current_state.is_empty() {
+                return longest;
+            }
+        }
+    }
 }
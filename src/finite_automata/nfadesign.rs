@@ -1,49 +1,349 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Debug;
 use std::hash::Hash;
+use std::iter::Peekable;
+use std::str::CharIndices;
+use std::sync::{Arc, OnceLock};
 
 use helper::{to_hashset};
 
+use super::classes::{SymbolClasses};
+use super::densedfa::{DenseDFA};
+use super::dfadesign::{DFADesign};
+use super::dfarulebook::{DFARulebook};
 use super::farule::{FARule};
-use super::nfa::{NFA};
+use super::lazydfa::{LazyDFA, LazyDFAOptions};
+use super::nfa::{NFA, PrefixStatus};
 use super::nfarulebook::{NFARulebook};
+use super::predicate::{Predicate, RangeSet};
+use super::snapshot::{StateIds};
+use super::symbol::{Symbol};
+use super::subset::{DEFAULT_STATE_LIMIT, SubsetDFA, SubsetError, subset_hash};
 
 #[derive(Clone)]
-pub struct NFADesign<T> {
+pub struct NFADesign<T, S = char, P = RangeSet<S>> {
     start_state: T,
     accept_states: HashSet<T>,
-    rulebook: NFARulebook<T>
+    rulebook: NFARulebook<T, S, P>,
+    /// See `live_states`, shared with every `NFA` of the design.
+    live_states: Arc<OnceLock<HashSet<T>>>,
 }
 
-impl<T: Eq + Clone + Hash> NFADesign<T> {
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug, P: Predicate<S>> NFADesign<T, S, P> {
     pub fn new(start_state: &T,
                accept_states: &HashSet<T>,
-               rulebook: &NFARulebook<T>) -> Self {
+               rulebook: &NFARulebook<T, S, P>) -> Self {
         NFADesign{
             start_state: start_state.clone(),
             accept_states: accept_states.clone(),
-            rulebook: rulebook.clone()
+            rulebook: rulebook.clone(),
+            live_states: Arc::new(OnceLock::new()),
         }
     }
 
-    pub fn to_nfa_with_state(&self, start_state: &HashSet<T>) -> NFA<T> {
-        NFA::new(
+    pub fn to_nfa_with_state(&self, start_state: &HashSet<T>) -> NFA<T, S, P> {
+        NFA::with_live_states(
             start_state,
             &self.accept_states,
-            &self.rulebook)
+            &self.rulebook,
+            &self.live_states)
     }
 
-    pub fn to_nfa(&self) -> NFA<T> {
-        self.to_nfa_with_state(&to_hashset(&[self.start_state.clone()]))
+    pub fn to_nfa(&self) -> NFA<T, S, P> {
+        self.to_nfa_with_state(&to_hashset(::std::slice::from_ref(&self.start_state)))
     }
 
-    pub fn accept(&self, s: &str) -> bool {
+    pub fn accept_symbols<I: IntoIterator<Item=S>>(&self, symbols: I) -> bool {
         let mut nfa = self.to_nfa();
-        nfa.read_string(s);
+        nfa.read_symbols(symbols);
         nfa.accepting()
     }
 
+    /// The states from which an accept state is still reachable, found
+    /// once per design.
+    pub fn live_states(&self) -> HashSet<T> {
+        self.live_states.get_or_init(|| self.rulebook.states_reaching(&self.accept_states)).clone()
+    }
+
+    /// The equivalent DFA by the subset construction, taken over the guards
+    /// of the rules rather than single symbols so it works for any alphabet.
+    /// Each DFA state is numbered in the order it was found from the start
+    /// state, the set of no NFA states being a dead state. Fails on designs
+    /// with `Look` rules.
+    pub fn determinize(&self) -> Result<DFADesign<usize, S, P>, SubsetError> {
+        self.to_dfa_design_with_limit(usize::MAX).map(|dfa| dfa.into_design())
+    }
+
+    /// Like `determinize`, keeping the NFA states behind each DFA state and
+    /// stopping past `DEFAULT_STATE_LIMIT` states.
+    pub fn to_dfa_design(&self) -> Result<SubsetDFA<T, S, P>, SubsetError> {
+        self.to_dfa_design_with_limit(DEFAULT_STATE_LIMIT)
+    }
+
+    /// Like `to_dfa_design`, stopping past `limit` states.
+    pub fn to_dfa_design_with_limit(&self, limit: usize) -> Result<SubsetDFA<T, S, P>, SubsetError> {
+        if self.rulebook.has_looks() {
+            return Err(SubsetError::LookRules);
+        }
+        if limit == 0 {
+            return Err(SubsetError::TooManyStates(limit));
+        }
+        let start_state = to_hashset(::std::slice::from_ref(&self.start_state));
+        let mut subsets = vec![self.rulebook.follow_free_moves(&start_state)];
+        let mut found: HashMap<u64, Vec<usize>> = HashMap::new();
+        found.entry(subset_hash(&subsets[0])).or_default().push(0);
+        let mut rules = Vec::new();
+        let mut i = 0;
+        while i < subsets.len() {
+            for (guard, next_states) in self.rulebook.guarded_moves(&subsets[i]) {
+                let next_states = self.rulebook.follow_free_moves(&next_states);
+                let candidates = found.entry(subset_hash(&next_states)).or_default();
+                let next = match candidates.iter().find(|&&j| subsets[j] == next_states) {
+                    Some(&next) => next,
+                    None => {
+                        if subsets.len() >= limit {
+                            return Err(SubsetError::TooManyStates(limit));
+                        }
+                        candidates.push(subsets.len());
+                        subsets.push(next_states);
+                        subsets.len() - 1
+                    },
+                };
+                rules.push(FARule::new_rulepredicate(&i, guard, &next));
+            }
+            i += 1;
+        }
+        let accept_states = subsets.iter().enumerate()
+            .filter(|&(_, subset)| !subset.is_disjoint(&self.accept_states))
+            .map(|(i, _)| i)
+            .collect::<Vec<usize>>();
+        let design = DFADesign::new(0, &accept_states, &DFARulebook::new(rules));
+        Ok(SubsetDFA::new(design, subsets))
+    }
+
     pub fn start_state(&self) -> T { self.start_state.clone() }
     pub fn accept_state(&self) -> HashSet<T> { self.accept_states.clone() }
-    pub fn rulebook(&self) -> NFARulebook<T> { self.rulebook.clone() }
-    pub fn rules(&self) -> Vec<FARule<T>> { self.rulebook.rules() }
+    pub fn rulebook(&self) -> NFARulebook<T, S, P> { self.rulebook.clone() }
+    pub fn rules(&self) -> Vec<FARule<T, S, P>> { self.rulebook.rules() }
+    pub fn has_looks(&self) -> bool { self.rulebook.has_looks() }
+}
+
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug + Hash, P: Predicate<S>> NFADesign<T, S, P> {
+    /// A DFA that builds its states from this design while reading. Fails
+    /// on designs with `Look` rules.
+    pub fn to_lazy_dfa(&self, options: LazyDFAOptions) -> Result<LazyDFA<T, S, P>, SubsetError> {
+        LazyDFA::new(self, options)
+    }
+}
+
+impl<T: Eq + Clone + Hash, S: Symbol> NFADesign<T, S> {
+    /// Stable ids of the states, to snapshot and restore a running `NFA`.
+    pub fn state_ids(&self) -> StateIds<T, S> {
+        let accept_states = self.accept_states.iter().cloned().collect::<Vec<T>>();
+        StateIds::new(&self.start_state, &accept_states, &self.rulebook.rules())
+    }
+
+    /// The classes of symbols the rules of the design cannot tell apart.
+    pub fn symbol_classes(&self) -> SymbolClasses<S> {
+        SymbolClasses::new(&self.rules())
+    }
+
+    /// The dense table DFA of the design, failing past
+    /// `DEFAULT_STATE_LIMIT` states. See `DenseDFA::new`.
+    pub fn to_dense_dfa(&self, anchored: bool) -> Result<DenseDFA<S>, SubsetError> {
+        DenseDFA::new(self, anchored, DEFAULT_STATE_LIMIT)
+    }
+}
+
+impl<T: Eq + Clone + Hash> NFADesign<T> {
+    /// Whether the design accepts the whole of `s`, taking the `Look` rules
+    /// whose assertion holds at each position.
+    pub fn accept(&self, s: &str) -> bool {
+        let mut states = to_hashset(::std::slice::from_ref(&self.start_state));
+        let mut prev = None;
+        let mut chars = s.chars().peekable();
+        loop {
+            let next = chars.peek().cloned();
+            states = self.rulebook.follow_free_moves_where(&states, |look| look.holds(prev, next));
+            match chars.next() {
+                Some(c) => states = self.rulebook.next_states(&states, Some(c)),
+                None => return !states.is_disjoint(&self.accept_states),
+            }
+            prev = next;
+        }
+    }
+
+    pub fn shortest_match(&self, s: &str) -> Option<usize> {
+        self.to_nfa().shortest_match(s)
+    }
+
+    pub fn longest_prefix_match(&self, s: &str) -> Option<usize> {
+        self.to_nfa().longest_prefix_match(s)
+    }
+
+    /// Whether `s` is accepted, could still be accepted with more input, or
+    /// can never be.
+    pub fn prefix_status(&self, s: &str) -> PrefixStatus {
+        let mut nfa = self.to_nfa();
+        for c in s.chars() {
+            nfa.read_character(c);
+            if nfa.prefix_status() == PrefixStatus::Dead {
+                return PrefixStatus::Dead;
+            }
+        }
+        nfa.prefix_status()
+    }
+
+    /// Byte offsets in `s` where a match of the design ends, with the match
+    /// allowed to start anywhere. Overlapping matches are all reported.
+    pub fn match_ends<'a>(&'a self, s: &'a str) -> MatchEnds<'a, T> {
+        MatchEnds::new(self, s, 0, false)
+    }
+
+    /// Byte offsets in `s` where a match starting at offset `start` ends.
+    pub fn prefix_match_ends<'a>(&'a self, s: &'a str, start: usize) -> MatchEnds<'a, T> {
+        MatchEnds::new(self, s, start, true)
+    }
+
+    /// The start and end byte offsets of every match of the design in `s`,
+    /// overlapping matches included, ordered by end and then by start.
+    pub fn match_spans<'a, 't>(&'a self, s: &'t str) -> MatchSpans<'a, 't, T> {
+        MatchSpans {
+            design: self,
+            chars: s.char_indices().peekable(),
+            prev: None,
+            pos: 0,
+            threads: Some(HashMap::new()),
+            found: Vec::new().into_iter(),
+        }
+    }
+}
+
+/// Iterator over the positions where the running automaton reaches one of
+/// its accept states.
+pub struct MatchEnds<'a, T: 'a> {
+    design: &'a NFADesign<T>,
+    chars: Peekable<CharIndices<'a>>,
+    prev: Option<char>,
+    start: usize,
+    pos: usize,
+    current_state: Option<HashSet<T>>,
+    anchored: bool,
+}
+
+impl<'a, T: Eq + Clone + Hash> MatchEnds<'a, T> {
+    fn new(design: &'a NFADesign<T>, s: &'a str, start: usize, anchored: bool) -> Self {
+        let mut chars = s.char_indices().peekable();
+        let mut prev = None;
+        while chars.peek().is_some_and(|&(i, _)| i < start) {
+            prev = chars.next().map(|(_, c)| c);
+        }
+        MatchEnds {
+            design,
+            chars,
+            prev,
+            start,
+            pos: start,
+            current_state: Some(HashSet::new()),
+            anchored,
+        }
+    }
+}
+
+impl<'a, T: Eq + Clone + Hash> Iterator for MatchEnds<'a, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        loop {
+            let mut states = self.current_state.take()?;
+            if !self.anchored || self.pos == self.start {
+                states.insert(self.design.start_state.clone());
+            }
+            let prev = self.prev;
+            let next = self.chars.peek().map(|&(_, c)| c);
+            let states = self.design.rulebook.follow_free_moves_where(&states, |look| look.holds(prev, next));
+            if states.is_empty() {
+                return None;
+            }
+            let pos = self.pos;
+            if let Some((i, c)) = self.chars.next() {
+                self.current_state = Some(self.design.rulebook.next_states(&states, Some(c)));
+                self.prev = Some(c);
+                self.pos = i + c.len_utf8();
+            }
+            if !states.is_disjoint(&self.design.accept_states) {
+                return Some(pos);
+            }
+        }
+    }
+}
+
+/// Iterator over the spans of all the matches in a text, found in one pass
+/// that runs every match in progress at once.
+pub struct MatchSpans<'a, 't, T: 'a> {
+    design: &'a NFADesign<T>,
+    chars: Peekable<CharIndices<'t>>,
+    prev: Option<char>,
+    pos: usize,
+    /// The states reached so far, each with the start offsets of the
+    /// matches in progress that reached it.
+    threads: Option<HashMap<T, BTreeSet<usize>>>,
+    /// The spans ending at the last position read, still to be reported.
+    found: ::std::vec::IntoIter<(usize, usize)>,
+}
+
+impl<'a, 't, T: Eq + Clone + Hash> MatchSpans<'a, 't, T> {
+    /// Carry the start offsets of every thread along the free moves and the
+    /// `Look` rules that hold between `prev` and `next`.
+    fn follow_free_moves(&self, threads: &mut HashMap<T, BTreeSet<usize>>, next: Option<char>) {
+        let rulebook = &self.design.rulebook;
+        let mut queue = threads.keys().cloned().collect::<Vec<T>>();
+        while let Some(state) = queue.pop() {
+            let starts = threads[&state].clone();
+            for rule in rulebook.free_rules_from(&state) {
+                if !rule.is_free() && !rule.look().is_some_and(|look| look.holds(self.prev, next)) {
+                    continue;
+                }
+                let reached = threads.entry(rule.next_state.clone()).or_default();
+                let before = reached.len();
+                reached.extend(starts.iter().cloned());
+                if reached.len() > before {
+                    queue.push(rule.next_state.clone());
+                }
+            }
+        }
+    }
+}
+
+impl<'a, 't, T: Eq + Clone + Hash> Iterator for MatchSpans<'a, 't, T> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<(usize, usize)> {
+        loop {
+            if let Some(span) = self.found.next() {
+                return Some(span);
+            }
+            let mut threads = self.threads.take()?;
+            threads.entry(self.design.start_state.clone()).or_default().insert(self.pos);
+            let next = self.chars.peek().map(|&(_, c)| c);
+            self.follow_free_moves(&mut threads, next);
+            let pos = self.pos;
+            let starts = self.design.accept_states.iter()
+                .filter_map(|state| threads.get(state))
+                .flat_map(|starts| starts.iter().cloned())
+                .collect::<BTreeSet<usize>>();
+            self.found = starts.into_iter().map(|start| (start, pos)).collect::<Vec<_>>().into_iter();
+            if let Some((i, c)) = self.chars.next() {
+                let mut next_threads: HashMap<T, BTreeSet<usize>> = HashMap::new();
+                for (state, starts) in &threads {
+                    for next_state in self.design.rulebook.follow_rules_for(state, Some(c)) {
+                        next_threads.entry(next_state).or_default().extend(starts.iter().cloned());
+                    }
+                }
+                self.threads = Some(next_threads);
+                self.prev = Some(c);
+                self.pos = i + c.len_utf8();
+            }
+        }
+    }
 }
@@ -1,19 +1,98 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Debug;
 use std::hash::Hash;
+use std::sync::Arc;
+
+use helper::{to_hashset};
 
 use super::farule::FARule;
+use super::look::{Look};
+use super::predicate::{Predicate, RangeSet, minterms};
+
+/// The rules of an NFA, indexed by the state they leave so a step only
+/// looks at the rules of the states it is in.
+#[derive(Clone)]
+pub struct NFARulebook<T, S = char, P = RangeSet<S>> {
+    rules: Vec<FARule<T, S, P>>,
+    transitions: HashMap<T, Transitions>,
+    /// How each state with free or tag rules reaches the states they lead
+    /// to, computed once so reading a symbol does no closure work.
+    closures: Arc<HashMap<T, Closure<T>>>,
+    has_looks: bool,
+    /// The line terminator symbols of the `Look` rules, with their
+    /// characters.
+    line_symbols: Vec<(S, char)>,
+}
+
+/// Indices into `rules` of the rules leaving one state, in rulebook order.
+#[derive(Clone,Default)]
+struct Transitions {
+    /// Rules reading a symbol.
+    consuming: Vec<usize>,
+    /// Free, tag and look rules, which read nothing.
+    free: Vec<usize>,
+}
 
+/// The states other than itself a state reaches by free and tag rules.
 #[derive(Clone)]
-pub struct NFARulebook<T> {
-    rules: Vec<FARule<T>>,
+enum Closure<T> {
+    /// The state has a single such rule, and reaches the next state and
+    /// what that state reaches. Chains of single rules share their states
+    /// this way instead of each holding a copy.
+    Link(T),
+    /// The state has several such rules, and reaches all of these states.
+    States(Vec<T>),
 }
 
-impl<T: Eq + Clone + Hash> NFARulebook<T> {
-    pub fn new(rules: Vec<FARule<T>>) -> Self {
-        NFARulebook{rules: rules}
+const NO_RULES: &[usize] = &[];
+
+impl<T: Eq + Clone + Hash, S: Copy + Ord + Debug, P: Predicate<S>> NFARulebook<T, S, P> {
+    pub fn new(rules: Vec<FARule<T, S, P>>) -> Self {
+        let mut transitions: HashMap<T, Transitions> = HashMap::new();
+        for (i, rule) in rules.iter().enumerate() {
+            let outgoing = transitions.entry(rule.state.clone()).or_default();
+            if rule.guard().is_some() {
+                outgoing.consuming.push(i);
+            } else {
+                outgoing.free.push(i);
+            }
+        }
+        let has_looks = rules.iter().any(|rule| rule.look().is_some());
+        let mut line_symbols = rules.iter().flat_map(|rule| rule.line_symbols().iter().cloned()).collect::<Vec<_>>();
+        line_symbols.sort();
+        line_symbols.dedup();
+        let mut rulebook = NFARulebook{rules, transitions, closures: Arc::default(), has_looks, line_symbols};
+        let mut closures = HashMap::new();
+        for state in rulebook.transitions.keys() {
+            let mut next_states = rulebook.follow_rules_for(state, None);
+            match next_states.len() {
+                0 => {},
+                1 => { closures.insert(state.clone(), Closure::Link(next_states.pop().unwrap())); },
+                _ => { closures.insert(state.clone(), Closure::States(rulebook.free_closure(state))); },
+            }
+        }
+        rulebook.closures = Arc::new(closures);
+        rulebook
     }
 
-    pub fn next_states(&self, states: &HashSet<T>, character: Option<char>) -> HashSet<T> {
+    /// The states other than `state` reached from it by free and tag rules.
+    fn free_closure(&self, state: &T) -> Vec<T> {
+        let mut seen = HashSet::new();
+        seen.insert(state.clone());
+        let mut closure = Vec::new();
+        let mut queue = vec![state.clone()];
+        while let Some(state) = queue.pop() {
+            for next_state in self.follow_rules_for(&state, None) {
+                if seen.insert(next_state.clone()) {
+                    closure.push(next_state.clone());
+                    queue.push(next_state);
+                }
+            }
+        }
+        closure
+    }
+
+    pub fn next_states(&self, states: &HashSet<T>, character: Option<S>) -> HashSet<T> {
         let mut next_states: HashSet<T> = HashSet::new();
         for state in states.iter() {
             for next_state in self.follow_rules_for(state, character) {
@@ -23,23 +102,156 @@ impl<T: Eq + Clone + Hash> NFARulebook<T> {
         next_states
     }
 
-    pub fn follow_rules_for(&self, state: &T, character: Option<char>) -> Vec<T> {
-        self.rules.iter()
-                  .filter(|rule| rule.applies_to(state, character))
-                  .map(|rule| rule.follow())
-                  .collect()
+    pub fn follow_rules_for(&self, state: &T, character: Option<S>) -> Vec<T> {
+        let indices = match character {
+            Some(_) => self.consuming_from(state),
+            None => self.free_from(state),
+        };
+        indices.iter()
+               .map(|&i| &self.rules[i])
+               .filter(|rule| rule.applies_to(state, character))
+               .map(|rule| rule.follow())
+               .collect()
     }
 
     pub fn follow_free_moves(&self, states: &HashSet<T>) -> HashSet<T>{
-        let more_states = self.next_states(states, None);
-        if more_states.is_subset(states) {
-            states.clone()
-        } else {
-            self.follow_free_moves(&states.union(&more_states)
-                .cloned()
-                .collect())
+        let mut closed = states.clone();
+        for start in states.iter() {
+            let mut state = start;
+            while let Some(closure) = self.closures.get(state) {
+                match *closure {
+                    // A state already closed over has been followed, or
+                    // will be as one of `states`.
+                    Closure::Link(ref next_state) => {
+                        if !closed.insert(next_state.clone()) {
+                            break;
+                        }
+                        state = next_state;
+                    },
+                    Closure::States(ref reached) => {
+                        closed.extend(reached.iter().cloned());
+                        break;
+                    },
+                }
+            }
+        }
+        closed
+    }
+
+    /// Like `follow_free_moves`, also following the rules whose `Look`
+    /// assertion `holds`.
+    pub fn follow_free_moves_where<F: Fn(Look) -> bool>(&self, states: &HashSet<T>, holds: F) -> HashSet<T> {
+        let mut closed = self.follow_free_moves(states);
+        if !self.has_looks {
+            return closed;
+        }
+        let mut queue = closed.iter().cloned().collect::<Vec<T>>();
+        while let Some(state) = queue.pop() {
+            for rule in self.free_from(&state).iter().map(|&i| &self.rules[i]) {
+                if !rule.look().is_some_and(&holds) || closed.contains(&rule.next_state) {
+                    continue;
+                }
+                let reached = self.follow_free_moves(&to_hashset(::std::slice::from_ref(&rule.next_state)));
+                for next_state in reached {
+                    if closed.insert(next_state.clone()) {
+                        queue.push(next_state);
+                    }
+                }
+            }
+        }
+        closed
+    }
+
+    /// Like `follow_free_moves`, also following the `Look` rules that hold
+    /// between the symbol `next` and a symbol seen as `prev`, see
+    /// `look_char`.
+    pub fn follow_free_moves_around(&self, states: &HashSet<T>, prev: Option<char>, next: Option<S>) -> HashSet<T> {
+        let next = next.map(|symbol| self.look_char(symbol));
+        self.follow_free_moves_where(states, |look| look.holds(prev, next))
+    }
+
+    /// The rules leaving `state`, in the order they were given.
+    pub fn rules_from(&self, state: &T) -> Vec<&FARule<T, S, P>> {
+        let mut indices = self.consuming_from(state).to_vec();
+        indices.extend_from_slice(self.free_from(state));
+        indices.sort_unstable();
+        indices.into_iter().map(|i| &self.rules[i]).collect()
+    }
+
+    /// The rules leaving `state` that read a symbol, in the order they were
+    /// given.
+    pub fn consuming_rules_from(&self, state: &T) -> Vec<&FARule<T, S, P>> {
+        self.consuming_from(state).iter().map(|&i| &self.rules[i]).collect()
+    }
+
+    /// The free, tag and look rules leaving `state`, in the order they were
+    /// given.
+    pub fn free_rules_from(&self, state: &T) -> Vec<&FARule<T, S, P>> {
+        self.free_from(state).iter().map(|&i| &self.rules[i]).collect()
+    }
+
+    fn consuming_from(&self, state: &T) -> &[usize] {
+        self.transitions.get(state).map_or(NO_RULES, |outgoing| &outgoing.consuming)
+    }
+
+    fn free_from(&self, state: &T) -> &[usize] {
+        self.transitions.get(state).map_or(NO_RULES, |outgoing| &outgoing.free)
+    }
+
+    /// The states from which one of `targets` can be reached by following
+    /// the rules forward, `targets` included.
+    pub fn states_reaching(&self, targets: &HashSet<T>) -> HashSet<T> {
+        let mut incoming: HashMap<&T, Vec<&T>> = HashMap::new();
+        for rule in self.rules.iter() {
+            incoming.entry(&rule.next_state).or_default().push(&rule.state);
+        }
+        let mut reaching = targets.clone();
+        let mut queue = targets.iter().cloned().collect::<Vec<T>>();
+        while let Some(state) = queue.pop() {
+            for &previous in incoming.get(&state).into_iter().flatten() {
+                if reaching.insert(previous.clone()) {
+                    queue.push(previous.clone());
+                }
+            }
         }
+        reaching
     }
 
-    pub fn rules(&self) -> Vec<FARule<T>> { self.rules.clone() }
+    /// The moves out of `states` on a symbol, computed over the guards
+    /// instead of symbols: every symbol satisfies exactly one of the
+    /// returned guards and moves to the states next to it, possibly none.
+    pub fn guarded_moves(&self, states: &HashSet<T>) -> Vec<(P, HashSet<T>)> {
+        let rules = states.iter()
+            .flat_map(|state| self.consuming_rules_from(state))
+            .filter_map(|rule| rule.guard().map(|guard| (guard, rule.follow())))
+            .collect::<Vec<(P, T)>>();
+        let guards = rules.iter().map(|rule| rule.0.clone()).collect::<Vec<P>>();
+        minterms(&guards).into_iter()
+            .map(|(guard, satisfied)| {
+                (guard, satisfied.into_iter().map(|i| rules[i].1.clone()).collect())
+            })
+            .collect()
+    }
+
+    pub fn rules(&self) -> Vec<FARule<T, S, P>> { self.rules.clone() }
+
+    /// The number of rules.
+    pub fn len(&self) -> usize { self.rules.len() }
+    pub fn is_empty(&self) -> bool { self.rules.is_empty() }
+
+    /// Whether some rule is a `Look` rule, which `follow_free_moves` never
+    /// takes.
+    pub fn has_looks(&self) -> bool { self.has_looks }
+
+    /// The symbols the `Look` rules look for. All other symbols are the
+    /// same to every `Look`.
+    pub fn line_symbols(&self) -> Vec<S> {
+        self.line_symbols.iter().map(|&(symbol, _)| symbol).collect()
+    }
+
+    /// The character the `Look` rules see for `symbol`: its own for line
+    /// terminators, `'\0'` for every other symbol.
+    pub fn look_char(&self, symbol: S) -> char {
+        self.line_symbols.iter().find(|&&(line, _)| line == symbol).map_or('\0', |&(_, c)| c)
+    }
 }
@@ -0,0 +1,102 @@
+use std::fmt::Debug;
+
+use super::faruledata::{FARuleData};
+use super::symbol::{Symbol};
+
+/// A guard over input symbols of type `S`. Guards form a boolean algebra,
+/// which lets an automaton labelled by guards be determinized without
+/// enumerating the symbols.
+pub trait Predicate<S>: Clone + Debug {
+    /// The guard every symbol satisfies.
+    fn top() -> Self;
+    /// The guard satisfied by the symbols in `ranges`, so character and set
+    /// rules can be mixed with predicate rules.
+    fn from_ranges(ranges: &[FARuleData<S>]) -> Self;
+
+    fn accepts(&self, symbol: &S) -> bool;
+    fn and(&self, other: &Self) -> Self;
+    fn or(&self, other: &Self) -> Self;
+    fn not(&self) -> Self;
+    /// Whether some symbol satisfies the guard.
+    fn is_satisfiable(&self) -> bool;
+
+    fn bottom() -> Self {
+        Self::top().not()
+    }
+}
+
+/// The guard satisfied by the symbols of a set of ranges, the default guard
+/// of `FARule`.
+#[derive(Debug,Clone,PartialEq)]
+pub struct RangeSet<S = char> {
+    ranges: Vec<FARuleData<S>>,
+}
+
+impl<S: Symbol> RangeSet<S> {
+    pub fn new(ranges: &[FARuleData<S>]) -> Self {
+        RangeSet { ranges: FARuleData::merge(ranges) }
+    }
+}
+
+impl<S> RangeSet<S> {
+    /// The merged ranges, sorted and disjoint.
+    pub fn ranges(&self) -> &[FARuleData<S>] { &self.ranges }
+}
+
+impl<S: Symbol> Predicate<S> for RangeSet<S> {
+    fn top() -> Self {
+        RangeSet::new(&[FARuleData::range(S::min_symbol(), S::max_symbol())])
+    }
+
+    fn from_ranges(ranges: &[FARuleData<S>]) -> Self {
+        RangeSet::new(ranges)
+    }
+
+    fn accepts(&self, symbol: &S) -> bool {
+        self.ranges.iter().any(|data| data.applies_to(symbol))
+    }
+
+    fn and(&self, other: &Self) -> Self {
+        self.not().or(&other.not()).not()
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        let mut ranges = self.ranges.clone();
+        ranges.extend(other.ranges.iter().cloned());
+        RangeSet::new(&ranges)
+    }
+
+    fn not(&self) -> Self {
+        RangeSet { ranges: FARuleData::complement(&self.ranges) }
+    }
+
+    fn is_satisfiable(&self) -> bool {
+        !self.ranges.is_empty()
+    }
+}
+
+/// Split the symbols into the satisfiable minterms of `guards`: every
+/// minterm is the conjunction of each guard or its negation, returned with
+/// the indices of the guards it satisfies. The minterms are disjoint and
+/// together cover every symbol.
+pub fn minterms<S, P: Predicate<S>>(guards: &[P]) -> Vec<(P, Vec<usize>)> {
+    let mut minterms = vec![(P::top(), Vec::new())];
+    for (i, guard) in guards.iter().enumerate() {
+        let negation = guard.not();
+        let mut next = Vec::new();
+        for (minterm, satisfied) in minterms {
+            let with = minterm.and(guard);
+            if with.is_satisfiable() {
+                let mut satisfied = satisfied.clone();
+                satisfied.push(i);
+                next.push((with, satisfied));
+            }
+            let without = minterm.and(&negation);
+            if without.is_satisfiable() {
+                next.push((without, satisfied));
+            }
+        }
+        minterms = next;
+    }
+    minterms
+}
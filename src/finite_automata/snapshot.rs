@@ -0,0 +1,173 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result;
+use std::hash::Hash;
+use std::marker::PhantomData;
+use std::str::FromStr;
+
+use helper::{fnv1a};
+
+use super::farule::{FARule};
+use super::predicate::{RangeSet};
+use super::symbol::{Symbol};
+
+/// Numbers the states of an automaton breadth-first from its start state,
+/// following the rules of each state in rulebook order. The numbering only
+/// depends on the shape of the automaton, so an automaton built again from
+/// the same pattern, even in another process, gets the same ids. Only
+/// automata guarded by ranges have them, other guards cannot be told
+/// apart.
+#[derive(Clone)]
+pub struct StateIds<T, S = char, P = RangeSet<S>> {
+    ids: HashMap<T, usize>,
+    states: Vec<T>,
+    fingerprint: u64,
+    alphabet: PhantomData<(S, P)>,
+}
+
+impl<T: Eq + Clone + Hash, S: Symbol> StateIds<T, S> {
+    pub fn new(start_state: &T, accept_states: &[T], rules: &[FARule<T, S>]) -> Self {
+        let mut rules_from: HashMap<T, Vec<&FARule<T, S>>> = HashMap::new();
+        for rule in rules {
+            rules_from.entry(rule.state.clone()).or_default().push(rule);
+        }
+
+        let mut ids = HashMap::new();
+        let mut states = vec![start_state.clone()];
+        ids.insert(start_state.clone(), 0);
+        // Each accept state and rule as numbers, the first telling them
+        // apart.
+        let mut description: Vec<u64> = Vec::new();
+        let mut i = 0;
+        while i < states.len() {
+            let state = states[i].clone();
+            if accept_states.contains(&state) {
+                description.extend_from_slice(&[0, i as u64]);
+            }
+            for rule in rules_from.get(&state).map_or(&[][..], |rules| &rules[..]) {
+                let next_id = *ids.entry(rule.next_state.clone()).or_insert_with(|| {
+                    states.push(rule.next_state.clone());
+                    states.len() - 1
+                });
+                description.extend_from_slice(&[1, i as u64, next_id as u64]);
+                description.extend(rule.codes());
+            }
+            i += 1;
+        }
+
+        StateIds {
+            ids,
+            states,
+            fingerprint: fnv1a(&description.iter().flat_map(|code| code.to_le_bytes()).collect::<Vec<u8>>()),
+            alphabet: PhantomData,
+        }
+    }
+}
+
+impl<T: Eq + Clone + Hash, S, P> StateIds<T, S, P> {
+    pub fn id(&self, state: &T) -> Option<usize> { self.ids.get(state).cloned() }
+    pub fn state(&self, id: usize) -> Option<&T> { self.states.get(id) }
+    pub fn len(&self) -> usize { self.states.len() }
+    pub fn is_empty(&self) -> bool { self.states.is_empty() }
+
+    /// Identifies the automaton, two automata with the same fingerprint
+    /// number their states the same way.
+    pub fn fingerprint(&self) -> u64 { self.fingerprint }
+
+    /// Record `states` of the automaton these ids were computed for.
+    pub fn snapshot<'a, I: IntoIterator<Item=&'a T>>(&self, states: I)
+                                                     -> ::std::result::Result<Snapshot, SnapshotError> where T: 'a {
+        let mut states = states.into_iter()
+            .map(|state| self.id(state).ok_or(SnapshotError::ForeignState))
+            .collect::<::std::result::Result<Vec<usize>, SnapshotError>>()?;
+        states.sort();
+        states.dedup();
+        Ok(Snapshot {
+            fingerprint: self.fingerprint,
+            states,
+            prev: None,
+        })
+    }
+
+    /// The states recorded in `snapshot`.
+    pub fn restore(&self, snapshot: &Snapshot) -> ::std::result::Result<Vec<T>, SnapshotError> {
+        if snapshot.fingerprint != self.fingerprint {
+            return Err(SnapshotError::OtherAutomaton);
+        }
+        snapshot.states.iter()
+            .map(|&id| self.state(id).cloned().ok_or(SnapshotError::UnknownState(id)))
+            .collect()
+    }
+}
+
+/// The state of a running automaton as plain data. Its text form is the
+/// fingerprint in hex followed by the state ids, e.g. `3f1c...:0,3,4`, and
+/// the code of `prev` if there is one, e.g. `3f1c...:0,3,4:10`.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct Snapshot {
+    pub fingerprint: u64,
+    pub states: Vec<usize>,
+    /// What the `Look` rules of an NFA see of the symbol read last, `None`
+    /// at the start of the input.
+    pub prev: Option<char>,
+}
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub enum SnapshotError {
+    /// The snapshot was taken from an automaton with another fingerprint.
+    OtherAutomaton,
+    /// The snapshot names a state id the automaton does not have.
+    UnknownState(usize),
+    /// A state to record is not one of the automaton's, e.g. one an NFA was
+    /// started in with `NFADesign::to_nfa_with_state`.
+    ForeignState,
+    /// The text is not a snapshot.
+    Malformed,
+}
+
+impl Display for Snapshot {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        write!(f, "{:016x}:{}", self.fingerprint,
+               self.states.iter().map(|id| id.to_string()).collect::<Vec<String>>().join(","))?;
+        match self.prev {
+            Some(c) => write!(f, ":{}", c as u32),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Snapshot {
+    type Err = SnapshotError;
+
+    fn from_str(s: &str) -> ::std::result::Result<Self, SnapshotError> {
+        let mut parts = s.splitn(3, ':');
+        let fingerprint = parts.next()
+            .and_then(|hex| u64::from_str_radix(hex, 16).ok())
+            .ok_or(SnapshotError::Malformed)?;
+        let states = match parts.next() {
+            Some("") => vec![],
+            Some(ids) => ids.split(',')
+                .map(|id| id.parse::<usize>().map_err(|_| SnapshotError::Malformed))
+                .collect::<::std::result::Result<Vec<usize>, SnapshotError>>()?,
+            None => return Err(SnapshotError::Malformed),
+        };
+        let prev = match parts.next() {
+            Some(code) => Some(code.parse::<u32>().ok().and_then(::std::char::from_u32).ok_or(SnapshotError::Malformed)?),
+            None => None,
+        };
+        Ok(Snapshot { fingerprint, states, prev })
+    }
+}
+
+impl Display for SnapshotError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            SnapshotError::OtherAutomaton => write!(f, "snapshot belongs to another automaton"),
+            SnapshotError::UnknownState(id) => write!(f, "snapshot has unknown state {}", id),
+            SnapshotError::ForeignState => write!(f, "state does not belong to the automaton"),
+            SnapshotError::Malformed => write!(f, "malformed snapshot"),
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use std::collections::HashSet;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt::Display;
+use std::fmt::Formatter;
+use std::fmt::Result;
+use std::hash::{Hash, Hasher};
+
+use super::dfadesign::{DFADesign};
+use super::predicate::{RangeSet};
+
+/// The most DFA states `NFADesign::to_dfa_design` builds before giving up.
+pub const DEFAULT_STATE_LIMIT: usize = 10_000;
+
+/// A DFA built from an NFA by the subset construction, with the set of NFA
+/// states behind each of its states.
+pub struct SubsetDFA<T, S = char, P = RangeSet<S>> {
+    design: DFADesign<usize, S, P>,
+    subsets: Vec<HashSet<T>>,
+}
+
+impl<T, S, P> SubsetDFA<T, S, P> {
+    pub fn new(design: DFADesign<usize, S, P>, subsets: Vec<HashSet<T>>) -> Self {
+        SubsetDFA { design, subsets }
+    }
+
+    pub fn design(&self) -> &DFADesign<usize, S, P> { &self.design }
+    pub fn into_design(self) -> DFADesign<usize, S, P> { self.design }
+
+    /// The NFA states the DFA state `state` stands for, empty for the dead
+    /// state.
+    pub fn nfa_states(&self, state: usize) -> Option<&HashSet<T>> {
+        self.subsets.get(state)
+    }
+
+    /// The number of DFA states.
+    pub fn len(&self) -> usize { self.subsets.len() }
+    pub fn is_empty(&self) -> bool { self.subsets.is_empty() }
+}
+
+/// Why the subset construction stopped.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum SubsetError {
+    /// The DFA needs more states than the limit given.
+    TooManyStates(usize),
+    /// The NFA has `Look` rules, which need the symbols around a position
+    /// rather than a state.
+    LookRules,
+}
+
+impl Display for SubsetError {
+    fn fmt(&self, f: &mut Formatter) -> Result {
+        match *self {
+            SubsetError::TooManyStates(limit) => write!(f, "DFA exceeds the limit of {} states", limit),
+            SubsetError::LookRules => write!(f, "a DFA cannot follow look-around rules"),
+        }
+    }
+}
+
+/// A hash of `set` that does not depend on its iteration order, to find a
+/// subset again without comparing it against every other.
+pub fn subset_hash<T: Hash>(set: &HashSet<T>) -> u64 {
+    set.iter().fold(0u64, |hash, state| {
+        let mut hasher = DefaultHasher::new();
+        state.hash(&mut hasher);
+        hash.wrapping_add(hasher.finish())
+    })
+}
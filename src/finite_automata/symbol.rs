@@ -0,0 +1,71 @@
+use std::char;
+use std::convert::TryFrom;
+use std::fmt::Debug;
+use std::hash::Hash;
+
+/// A symbol of the input alphabet of an automaton. Symbols are totally
+/// ordered, so sets of them can be written as ranges.
+pub trait Symbol: Copy + Ord + Hash + Debug {
+    fn min_symbol() -> Self;
+    fn max_symbol() -> Self;
+    /// The next symbol in order, `None` after the last one.
+    fn successor(self) -> Option<Self>;
+    /// The previous symbol in order, `None` before the first one.
+    fn predecessor(self) -> Option<Self>;
+    /// The symbol with the code of `c`, which is how the line anchors
+    /// find line terminators. `None` if there is none.
+    fn from_char(_c: char) -> Option<Self> { None }
+    /// The number of symbols before this one, which identifies it in
+    /// fingerprints.
+    fn code(self) -> u64 {
+        let mut code = 0;
+        let mut symbol = self;
+        while let Some(previous) = symbol.predecessor() {
+            code += 1;
+            symbol = previous;
+        }
+        code
+    }
+}
+
+/// Characters are the Unicode scalar values, the surrogate code points are
+/// skipped.
+impl Symbol for char {
+    fn min_symbol() -> Self { '\0' }
+    fn max_symbol() -> Self { char::MAX }
+
+    fn successor(self) -> Option<Self> {
+        match self {
+            '\u{D7FF}' => Some('\u{E000}'),
+            char::MAX => None,
+            _ => char::from_u32(self as u32 + 1),
+        }
+    }
+
+    fn predecessor(self) -> Option<Self> {
+        match self {
+            '\u{E000}' => Some('\u{D7FF}'),
+            '\0' => None,
+            _ => char::from_u32(self as u32 - 1),
+        }
+    }
+
+    fn from_char(c: char) -> Option<Self> { Some(c) }
+    /// The code point, surrogates are not skipped.
+    fn code(self) -> u64 { u64::from(self as u32) }
+}
+
+macro_rules! integer_symbol {
+    ($($t:ty)*) => ($(
+        impl Symbol for $t {
+            fn min_symbol() -> Self { <$t>::MIN }
+            fn max_symbol() -> Self { <$t>::MAX }
+            fn successor(self) -> Option<Self> { self.checked_add(1) }
+            fn predecessor(self) -> Option<Self> { self.checked_sub(1) }
+            fn from_char(c: char) -> Option<Self> { <$t>::try_from(c as u32).ok() }
+            fn code(self) -> u64 { self as u64 }
+        }
+    )*)
+}
+
+integer_symbol! { u8 u16 u32 u64 usize }
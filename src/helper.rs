@@ -5,7 +5,15 @@ pub fn to_hashset<T: Eq + Clone + Hash>(arr: &[T]) -> HashSet<T> {
     arr.iter().cloned().collect::<HashSet<T>>()
 }
 
+#[allow(dead_code)]
 pub fn hashset_eq<T: Eq + Clone + Hash>(
     set1: &HashSet<T>, set2: &HashSet<T>) -> bool {
-    set1.is_subset(set2) && set1.is_superset(&set2)
+    set1.is_subset(set2) && set1.is_superset(set2)
+}
+
+/// 64-bit FNV-1a, a hash that stays the same across processes and builds.
+pub fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+    })
 }
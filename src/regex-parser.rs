@@ -4,14 +4,14 @@ extern crate pest;
 extern crate pest_derive;
 
 use nfa_regex::finite_automata::faruledata::{FARuleData};
-use nfa_regex::regular_expressions::regex::{Regex};
+use nfa_regex::regular_expressions::regex::{Anchor, Regex};
 use nfa_regex::regular_expressions::tonfa::{ToNFA};
 use pest::Parser;
 use pest::iterators::{Pair};
 use std::env;
 
 #[cfg(debug_assertions)]
-const _GRAMMER: &'static str = include_str!("regex.pest");
+const _GRAMMER: &str = include_str!("regex.pest");
 
 #[derive(Parser)]
 #[grammar = "regex.pest"]
@@ -35,6 +35,29 @@ pub fn main() {
     }
 }
 
+/// The inline flags in effect, set by `(?ms)` for the rest of the
+/// alternative or by `(?ms:...)` for the group.
+#[derive(Clone,Copy,Default)]
+struct Flags {
+    /// `m`: `^` and `$` match at line boundaries.
+    multi_line: bool,
+    /// `s`: `.` also matches line terminators.
+    dot_all: bool,
+}
+
+impl Flags {
+    fn set(mut self, flags: Pair<Rule>) -> Flags {
+        for flag in flags.into_inner().filter(|pair| pair.as_rule() == Rule::flag) {
+            match flag.into_span().as_str() {
+                "m" => self.multi_line = true,
+                "s" => self.dot_all = true,
+                flag => unreachable!("Unexpected flag: {}", flag),
+            }
+        }
+        self
+    }
+}
+
 fn build_set(pair: Pair<Rule>, reverse: bool) -> Box<Regex> {
     let mut set = Vec::new();
     let inner = pair.into_inner();
@@ -58,11 +81,27 @@ fn build_set(pair: Pair<Rule>, reverse: bool) -> Box<Regex> {
 }
 
 fn build_regex(pair: Pair<Rule>) -> Box<Regex> {
+    build_regex_with(pair, Flags::default())
+}
+
+fn build_regex_with(pair: Pair<Rule>, flags: Flags) -> Box<Regex> {
     println!("rule: {:?}", pair.as_rule());
     match pair.as_rule() {
         Rule::empty => Regex::empty(),
+        Rule::dot if flags.dot_all => Regex::any(),
+        Rule::dot => Regex::any_but_newline(),
+        Rule::start_anchor if flags.multi_line => Regex::anchor(Anchor::StartLine),
+        Rule::start_anchor => Regex::anchor(Anchor::StartText),
+        Rule::end_anchor if flags.multi_line => Regex::anchor(Anchor::EndLine),
+        Rule::end_anchor => Regex::anchor(Anchor::EndText),
+        Rule::flag_group => {
+            let choose = pair.clone().into_inner().find(|pair| pair.as_rule() == Rule::choose).unwrap();
+            build_regex_with(choose, flags.set(pair))
+        },
         Rule::character => Regex::literal(pair
             .into_span().as_str().chars().next().unwrap()),
+        Rule::byte => Regex::byte(u8::from_str_radix(&pair
+            .into_span().as_str()[2..], 16).unwrap()),
         Rule::reverse_set => {
             let mut inner = pair.into_inner();
             let may_op = inner.next().unwrap();
@@ -74,7 +113,7 @@ fn build_regex(pair: Pair<Rule>) -> Box<Regex> {
         }
         Rule::repeat => {
             let mut inner = pair.into_inner();
-            let regex = build_regex(inner.next().unwrap());
+            let regex = build_regex_with(inner.next().unwrap(), flags);
             match inner.next() {
                 Some(pair) => match pair.as_rule() {
                     Rule::op_repeat => Regex::repeat(regex),
@@ -87,19 +126,27 @@ fn build_regex(pair: Pair<Rule>) -> Box<Regex> {
         },
         Rule::choose => {
             let mut inner = pair.into_inner();
-            let fst = build_regex(inner.next().unwrap());
+            let fst = build_regex_with(inner.next().unwrap(), flags);
             match inner.next() {
                 Some(rest) => {
-                    Regex::choose(fst, build_regex(rest))
+                    Regex::choose(fst, build_regex_with(rest, flags))
                 },
                 None => fst,
             }
         },
         Rule::concat => {
             let mut inner = pair.into_inner();
-            let fst = build_regex(inner.next().unwrap());
+            let fst = inner.next().unwrap();
+            if fst.as_rule() == Rule::flags {
+                let flags = flags.set(fst);
+                return match inner.next() {
+                    Some(rest) => build_regex_with(rest, flags),
+                    None => Regex::empty(),
+                };
+            }
+            let fst = build_regex_with(fst, flags);
             match inner.next() {
-                Some(rest) => Regex::concatenate(fst, build_regex(rest)),
+                Some(rest) => Regex::concatenate(fst, build_regex_with(rest, flags)),
                 None => fst,
             }
         },
@@ -229,4 +276,45 @@ mod tests {
         assert!(pattern.matches("abcd"));
         assert!(!pattern.matches("abcdefg"));
     }
+
+    #[test]
+    fn test_regexparser_byte() {
+        let pair = RegexParser::parse(Rule::choose, "a\\x41")
+                    .unwrap_or_else(|e| panic!("{}", e))
+                    .next().unwrap();
+        let pattern = build_regex(pair);
+        assert!(pattern.matches("aA"));
+        assert!(!pattern.matches("a"));
+    }
+
+    #[test]
+    fn test_regexparser_modes() {
+        let pair = RegexParser::parse(Rule::choose, "a.c")
+                    .unwrap_or_else(|e| panic!("{}", e))
+                    .next().unwrap();
+        let pattern = build_regex(pair);
+        assert!(pattern.matches("abc"));
+        assert!(!pattern.matches("a\nc"));
+
+        let pair = RegexParser::parse(Rule::choose, "a(?s).c")
+                    .unwrap_or_else(|e| panic!("{}", e))
+                    .next().unwrap();
+        let pattern = build_regex(pair);
+        assert_eq!("a(?s:.)c", format!("{}", pattern));
+        assert!(pattern.matches("a\nc"));
+
+        let pair = RegexParser::parse(Rule::choose, "^a$(?m:^b$)")
+                    .unwrap_or_else(|e| panic!("{}", e))
+                    .next().unwrap();
+        let pattern = build_regex(pair);
+        assert_eq!("^a$(?m:^)b(?m:$)", format!("{}", pattern));
+        assert!(!pattern.matches("ab"));
+
+        let pair = RegexParser::parse(Rule::choose, "(?m)a$")
+                    .unwrap_or_else(|e| panic!("{}", e))
+                    .next().unwrap();
+        let pattern = build_regex(pair);
+        assert_eq!("a(?m:$)", format!("{}", pattern));
+        assert!(pattern.matches("a"));
+    }
 }
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use super::options::{MatchKind};
+
+/// Finds any of a set of literal strings in one pass over the text, the
+/// trie of the strings linked to the longest suffix of each node that is
+/// also in the trie. Matches are reported like the alternation of the
+/// strings would report them.
+#[derive(Debug,Clone)]
+pub struct AhoCorasick {
+    nodes: Vec<Node>,
+    /// The byte length of each string.
+    lengths: Vec<usize>,
+    kind: MatchKind,
+}
+
+#[derive(Debug,Clone,Default)]
+struct Node {
+    next: HashMap<char, usize>,
+    fail: usize,
+    /// The byte length of the text the node stands for.
+    depth: usize,
+    /// The strings ending at this node, its own and those of its suffixes.
+    outputs: Vec<usize>,
+}
+
+impl AhoCorasick {
+    pub fn new<I: AsRef<str>>(patterns: &[I], kind: MatchKind) -> Self {
+        let mut nodes = vec![Node::default()];
+        let mut lengths = Vec::new();
+        for (i, pattern) in patterns.iter().enumerate() {
+            let pattern = pattern.as_ref();
+            let mut node = 0;
+            for c in pattern.chars() {
+                node = match nodes[node].next.get(&c) {
+                    Some(&next) => next,
+                    None => {
+                        let depth = nodes[node].depth + c.len_utf8();
+                        nodes.push(Node { depth, ..Node::default() });
+                        let next = nodes.len() - 1;
+                        nodes[node].next.insert(c, next);
+                        next
+                    },
+                };
+            }
+            nodes[node].outputs.push(i);
+            lengths.push(pattern.len());
+        }
+
+        // Breadth-first, so the suffix a node fails to is always done.
+        let mut queue = nodes[0].next.values().cloned().collect::<VecDeque<usize>>();
+        let empty = nodes[0].outputs.clone();
+        for &child in queue.iter() {
+            nodes[child].outputs.extend(empty.iter().cloned());
+        }
+        while let Some(node) = queue.pop_front() {
+            let children = nodes[node].next.iter().map(|(&c, &child)| (c, child)).collect::<Vec<(char, usize)>>();
+            for (c, child) in children {
+                let mut fail = nodes[node].fail;
+                while fail != 0 && !nodes[fail].next.contains_key(&c) {
+                    fail = nodes[fail].fail;
+                }
+                let fail = nodes[fail].next.get(&c).cloned().unwrap_or(0);
+                nodes[child].fail = fail;
+                let inherited = nodes[fail].outputs.clone();
+                nodes[child].outputs.extend(inherited);
+                queue.push_back(child);
+            }
+        }
+        AhoCorasick { nodes, lengths, kind }
+    }
+
+    /// The number of strings.
+    pub fn len(&self) -> usize { self.lengths.len() }
+    pub fn is_empty(&self) -> bool { self.lengths.is_empty() }
+
+    fn next_state(&self, mut node: usize, c: char) -> usize {
+        loop {
+            if let Some(&next) = self.nodes[node].next.get(&c) {
+                return next;
+            }
+            if node == 0 {
+                return 0;
+            }
+            node = self.nodes[node].fail;
+        }
+    }
+
+    /// The leftmost match starting at or after byte offset `start`, as byte
+    /// offsets, with the index of the string matched. A `start` inside a
+    /// character moves on to the next character.
+    pub fn find_at(&self, s: &str, start: usize) -> Option<(usize, usize, usize)> {
+        let start = (start..=s.len()).find(|&i| s.is_char_boundary(i))?;
+        let mut node = 0;
+        let mut pos = start;
+        let mut best: Option<(usize, usize, usize)> = None;
+        let mut chars = s[start..].chars();
+        loop {
+            for &i in self.nodes[node].outputs.iter() {
+                let m = (pos - self.lengths[i], pos, i);
+                let better = match best {
+                    None => true,
+                    Some(b) => m.0 < b.0 || (m.0 == b.0 && match self.kind {
+                        MatchKind::LeftmostFirst => m.2 < b.2,
+                        MatchKind::LeftmostLongest => m.1 > b.1,
+                    }),
+                };
+                if better {
+                    best = Some(m);
+                }
+            }
+            // No later match can start as far left as the best one.
+            if best.is_some_and(|b| pos - self.nodes[node].depth > b.0) {
+                break;
+            }
+            match chars.next() {
+                Some(c) => {
+                    node = self.next_state(node, c);
+                    pos += c.len_utf8();
+                },
+                None => break,
+            }
+        }
+        best
+    }
+}
@@ -0,0 +1,98 @@
+use std::ops::Range;
+
+use super::options::{CompileOptions};
+use super::pikevm::{PikeVM};
+use super::regex::{Regex};
+use super::state::{StateId};
+use super::tonfa::{ToNFA};
+
+/// A match in a byte string, as byte offsets.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Match<'t> {
+    haystack: &'t [u8],
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    pub fn start(&self) -> usize { self.start }
+    pub fn end(&self) -> usize { self.end }
+    pub fn range(&self) -> Range<usize> { self.start..self.end }
+    pub fn as_bytes(&self) -> &'t [u8] { &self.haystack[self.range()] }
+}
+
+/// A `Regex` compiled for searching byte strings that need not be UTF-8.
+/// Characters of the pattern, sets and `Any` match UTF-8 encoded text,
+/// while `Regex::Byte` matches one raw byte.
+#[derive(Clone)]
+pub struct BytesRegex {
+    vm: PikeVM<StateId>,
+}
+
+impl BytesRegex {
+    pub fn new(regex: &Regex) -> Self {
+        BytesRegex::with_options(regex, &CompileOptions::default())
+    }
+
+    pub fn with_options(regex: &Regex, options: &CompileOptions) -> Self {
+        BytesRegex {
+            vm: PikeVM::new(&regex.to_byte_nfa_design_with(options.line_terminator), 2, options.match_kind),
+        }
+    }
+
+    pub fn is_match(&self, s: &[u8]) -> bool {
+        self.find(s).is_some()
+    }
+
+    pub fn find<'t>(&self, s: &'t [u8]) -> Option<Match<'t>> {
+        self.find_at(s, 0)
+    }
+
+    pub fn find_at<'t>(&self, s: &'t [u8], start: usize) -> Option<Match<'t>> {
+        self.vm.search_bytes(s, start).map(|slots| Match {
+            haystack: s,
+            start: slots[0].unwrap(),
+            end: slots[1].unwrap(),
+        })
+    }
+
+    pub fn find_iter<'r, 't>(&'r self, s: &'t [u8]) -> Matches<'r, 't> {
+        Matches {
+            regex: self,
+            haystack: s,
+            last_end: 0,
+            last_match: None,
+        }
+    }
+}
+
+/// Iterator over the successive non-overlapping matches in a byte string.
+pub struct Matches<'r, 't> {
+    regex: &'r BytesRegex,
+    haystack: &'t [u8],
+    last_end: usize,
+    last_match: Option<usize>,
+}
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        loop {
+            if self.last_end > self.haystack.len() {
+                return None;
+            }
+            let m = self.regex.find_at(self.haystack, self.last_end)?;
+            if m.start == m.end {
+                self.last_end = m.end + 1;
+                if Some(m.end) == self.last_match {
+                    continue;
+                }
+            } else {
+                self.last_end = m.end;
+            }
+            self.last_match = Some(m.end);
+            return Some(m);
+        }
+    }
+}
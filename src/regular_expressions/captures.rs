@@ -0,0 +1,111 @@
+use std::collections::HashMap;
+use std::ops::Index;
+use std::ops::Range;
+use std::sync::Arc;
+
+/// A single match of a pattern, as byte offsets into the searched text.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Match<'t> {
+    text: &'t str,
+    start: usize,
+    end: usize,
+}
+
+impl<'t> Match<'t> {
+    pub fn new(text: &'t str, start: usize, end: usize) -> Self {
+        Match { text, start, end }
+    }
+
+    pub fn start(&self) -> usize { self.start }
+    pub fn end(&self) -> usize { self.end }
+    pub fn range(&self) -> Range<usize> { self.start..self.end }
+    pub fn as_str(&self) -> &'t str { &self.text[self.range()] }
+}
+
+/// The spans of every capture group for one match. Group 0 is the whole
+/// match, groups that did not take part in the match are `None`.
+#[derive(Debug,Clone)]
+pub struct Captures<'t> {
+    text: &'t str,
+    slots: Vec<Option<usize>>,
+    names: Arc<HashMap<String, usize>>,
+}
+
+impl<'t> Captures<'t> {
+    pub fn new(text: &'t str, slots: Vec<Option<usize>>,
+               names: Arc<HashMap<String, usize>>) -> Self {
+        Captures { text, slots, names }
+    }
+
+    pub fn get(&self, i: usize) -> Option<Match<'t>> {
+        match (self.slots.get(2 * i), self.slots.get(2 * i + 1)) {
+            (Some(&Some(start)), Some(&Some(end))) => Some(Match::new(self.text, start, end)),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self, name: &str) -> Option<Match<'t>> {
+        self.names.get(name).and_then(|&i| self.get(i))
+    }
+
+    /// Number of groups, including the group 0 of the whole match.
+    pub fn len(&self) -> usize { self.slots.len() / 2 }
+    pub fn is_empty(&self) -> bool { self.slots.is_empty() }
+
+    /// Append `template` to `dst`, replacing `$1`, `${1}`, `$name` and
+    /// `${name}` with the text of that group. A group that does not exist or
+    /// did not participate expands to nothing, `$$` is a literal `$`.
+    pub fn expand(&self, template: &str, dst: &mut String) {
+        let mut rest = template;
+        while let Some(i) = rest.find('$') {
+            dst.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            if rest.starts_with('$') {
+                dst.push('$');
+                rest = &rest[1..];
+                continue;
+            }
+            let (name, remain) = if rest.starts_with('{') {
+                match rest.find('}') {
+                    Some(end) => (&rest[1..end], &rest[end + 1..]),
+                    None => ("", rest),
+                }
+            } else {
+                let end = rest.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+                              .unwrap_or(rest.len());
+                (&rest[..end], &rest[end..])
+            };
+            if name.is_empty() {
+                dst.push('$');
+                continue;
+            }
+            let group = match name.parse::<usize>() {
+                Ok(i) => self.get(i),
+                Err(_) => self.name(name),
+            };
+            if let Some(m) = group {
+                dst.push_str(m.as_str());
+            }
+            rest = remain;
+        }
+        dst.push_str(rest);
+    }
+}
+
+impl<'t> Index<usize> for Captures<'t> {
+    type Output = str;
+
+    fn index(&self, i: usize) -> &str {
+        self.get(i).map(|m| m.as_str())
+            .unwrap_or_else(|| panic!("no group at index '{}'", i))
+    }
+}
+
+impl<'t, 'i> Index<&'i str> for Captures<'t> {
+    type Output = str;
+
+    fn index(&self, name: &'i str) -> &str {
+        self.name(name).map(|m| m.as_str())
+            .unwrap_or_else(|| panic!("no group named '{}'", name))
+    }
+}
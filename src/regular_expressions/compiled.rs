@@ -0,0 +1,416 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::io::Read;
+use std::sync::{Arc, OnceLock};
+
+use finite_automata::densedfa::{DenseDFA};
+use finite_automata::nfadesign::{NFADesign, MatchEnds, MatchSpans};
+use super::ahocorasick::{AhoCorasick};
+use super::captures::{Captures, Match};
+use super::literals::{Literals};
+use super::options::{CompileOptions, Construction};
+use super::pikevm::{PikeVM};
+use super::positions::{Positions};
+use super::regex::{Regex};
+use super::replace::{Replacer};
+use super::shiftand::{ShiftAnd, MAX_POSITIONS};
+use super::stream::{ReadMatches, StreamMatcher};
+use super::state::{StateId};
+use super::tonfa::{ToNFA};
+
+/// The most states of the `is_match` DFA, beyond which the Pike VM is
+/// used.
+const DFA_STATE_LIMIT: usize = 1000;
+
+/// The most rules of a Thompson automaton the `is_match` DFA is built for,
+/// as every DFA state steps all the NFA states it holds.
+const DFA_RULE_LIMIT: usize = 2000;
+
+/// A `Regex` compiled once for searching inside text, rather than
+/// matching a whole string like `ToNFA::matches`.
+#[derive(Clone)]
+pub struct CompiledRegex {
+    regex: Arc<Regex>,
+    options: CompileOptions,
+    /// The Thompson or Glushkov automaton and its Pike VM. Built with the
+    /// `CompiledRegex`, unless the Aho-Corasick automaton answers searches
+    /// and they are only needed by the first call that asks for them.
+    engines: Arc<OnceLock<Engines>>,
+    /// The bit-parallel engine answering `is_match` and `shortest_match`,
+    /// if the pattern has no anchors and few enough positions.
+    shift_and: Option<Arc<ShiftAnd>>,
+    /// The unanchored dense DFA answering `is_match`, built by the first
+    /// `is_match` that needs it. `None` if the pattern has anchors or the
+    /// automaton is too large.
+    dfa: Arc<OnceLock<Option<DenseDFA>>>,
+    /// Built like `engines`.
+    literals: Arc<OnceLock<Literals>>,
+    /// The strings of a pattern that only chooses between literal strings,
+    /// searched for without the Pike VM.
+    alternatives: Option<Arc<AhoCorasick>>,
+    names: Vec<Option<String>>,
+    name_index: Arc<HashMap<String, usize>>,
+}
+
+struct Engines {
+    design: NFADesign<StateId>,
+    vm: PikeVM<StateId>,
+    construction: Construction,
+}
+
+impl CompiledRegex {
+    pub fn new(regex: &Regex) -> Self {
+        CompiledRegex::with_options(regex, &CompileOptions::default())
+    }
+
+    pub fn with_options(regex: &Regex, options: &CompileOptions) -> Self {
+        let names = regex.capture_names();
+        let name_index = names.iter().enumerate()
+            .filter_map(|(i, name)| name.clone().map(|name| (name, i)))
+            .collect();
+        let alternatives = regex.literal_alternatives()
+            .map(|alternatives| Arc::new(AhoCorasick::new(&alternatives, options.match_kind)));
+        let shift_and = if alternatives.is_none() {
+            Positions::with_limit(regex, options.line_terminator, MAX_POSITIONS)
+                .and_then(|positions| ShiftAnd::new(&positions))
+                .map(Arc::new)
+        } else {
+            None
+        };
+        let compiled = CompiledRegex {
+            regex: Arc::new(regex.clone()),
+            options: options.clone(),
+            engines: Arc::default(),
+            shift_and,
+            dfa: Arc::default(),
+            literals: Arc::default(),
+            alternatives,
+            names,
+            name_index: Arc::new(name_index),
+        };
+        if compiled.alternatives.is_none() {
+            compiled.engines();
+        }
+        compiled
+    }
+
+    fn engines(&self) -> &Engines {
+        self.engines.get_or_init(|| {
+            let options = &self.options;
+            let glushkov = match options.construction {
+                Construction::Glushkov if self.names.len() == 1 => {
+                    self.regex.to_glushkov_nfa_design_with(options.line_terminator)
+                },
+                _ => None,
+            };
+            let (design, construction) = match glushkov {
+                Some(design) => (design, Construction::Glushkov),
+                None => (self.regex.to_nfa_design_with(options.line_terminator), Construction::Thompson),
+            };
+            let vm = PikeVM::new(&design, 2 * self.names.len(), options.match_kind)
+                .with_prefix(&self.literals().prefix);
+            Engines { design, vm, construction }
+        })
+    }
+
+    /// The pattern matching any of `literals`, earlier ones first when
+    /// several match at one position under leftmost-first semantics.
+    pub fn from_literals<I: AsRef<str>>(literals: &[I], options: &CompileOptions) -> Self {
+        let branches = literals.iter().map(|literal| Regex::literal_string(literal.as_ref())).collect();
+        CompiledRegex::with_options(&Regex::alternation(branches), options)
+    }
+
+    /// The construction the automaton was built with, Thompson when the
+    /// Glushkov construction was asked for but cannot handle the pattern.
+    pub fn construction(&self) -> Construction { self.engines().construction }
+
+    /// Names of the capture groups, indexed by group number.
+    pub fn capture_names(&self) -> &[Option<String>] { &self.names }
+    pub fn captures_len(&self) -> usize { self.names.len() }
+
+    /// The literals every match starts with or contains.
+    pub fn literals(&self) -> &Literals {
+        self.literals.get_or_init(|| Literals::new(&self.regex))
+    }
+
+    /// Whether `s` holds the literal every match contains, which rules out
+    /// a match without running any automaton. The Aho-Corasick automaton
+    /// needs no such filter.
+    fn may_match(&self, s: &str) -> bool {
+        self.alternatives.is_some() || s.contains(self.literals().required.as_str())
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        if !self.may_match(s) {
+            return false;
+        }
+        if let Some(ref shift_and) = self.shift_and {
+            return shift_and.is_match(s);
+        }
+        if self.alternatives.is_some() {
+            return self.find(s).is_some();
+        }
+        match *self.dfa.get_or_init(|| self.build_dfa()) {
+            Some(ref dfa) => dfa.is_match(s),
+            None => self.find(s).is_some(),
+        }
+    }
+
+    fn build_dfa(&self) -> Option<DenseDFA> {
+        let design = &self.engines().design;
+        if design.rulebook().len() > DFA_RULE_LIMIT {
+            return None;
+        }
+        DenseDFA::new(design, false, DFA_STATE_LIMIT).ok()
+    }
+
+    /// The end of the first match to complete, without looking for where
+    /// it starts or how far it could extend.
+    pub fn shortest_match(&self, s: &str) -> Option<usize> {
+        if !self.may_match(s) {
+            return None;
+        }
+        if let Some(ref shift_and) = self.shift_and {
+            return shift_and.shortest_match(s);
+        }
+        self.engines().design.match_ends(s).next()
+    }
+
+    pub fn find<'t>(&self, s: &'t str) -> Option<Match<'t>> {
+        self.find_at(s, 0)
+    }
+
+    pub fn find_at<'t>(&self, s: &'t str, start: usize) -> Option<Match<'t>> {
+        self.captures_at(s, start).and_then(|caps| caps.get(0))
+    }
+
+    pub fn find_iter<'r, 't>(&'r self, s: &'t str) -> Matches<'r, 't> {
+        Matches(self.captures_iter(s))
+    }
+
+    pub fn captures<'t>(&self, s: &'t str) -> Option<Captures<'t>> {
+        self.captures_at(s, 0)
+    }
+
+    pub fn captures_at<'t>(&self, s: &'t str, start: usize) -> Option<Captures<'t>> {
+        if let Some(ref alternatives) = self.alternatives {
+            return alternatives.find_at(s, start)
+                .map(|(start, end, _)| Captures::new(s, vec![Some(start), Some(end)], self.name_index.clone()));
+        }
+        if !self.may_match(&s[start..]) {
+            return None;
+        }
+        self.engines().vm.search(s, start)
+            .map(|slots| Captures::new(s, slots, self.name_index.clone()))
+    }
+
+    pub fn captures_iter<'r, 't>(&'r self, s: &'t str) -> CaptureMatches<'r, 't> {
+        CaptureMatches {
+            regex: self,
+            text: s,
+            last_end: 0,
+            last_match: None,
+        }
+    }
+
+    /// Every byte offset where some match ends, overlapping matches
+    /// included.
+    pub fn match_ends<'r>(&'r self, s: &'r str) -> MatchEnds<'r, StateId> {
+        self.engines().design.match_ends(s)
+    }
+
+    /// Every match in `s`, including overlapping and nested ones, ordered by
+    /// end and then by start.
+    pub fn find_overlapping<'r, 't>(&'r self, s: &'t str) -> OverlappingMatches<'r, 't> {
+        OverlappingMatches {
+            text: s,
+            spans: self.engines().design.match_spans(s),
+        }
+    }
+
+    /// A matcher to feed the input to chunk by chunk.
+    pub fn stream_matcher(&self) -> StreamMatcher<'_> {
+        StreamMatcher::new(self)
+    }
+
+    /// The successive non-overlapping matches in everything `reader`
+    /// produces, as byte ranges from the start of the stream.
+    pub fn find_iter_read<R: Read>(&self, reader: R) -> ReadMatches<'_, R> {
+        ReadMatches::new(self, reader)
+    }
+
+    pub(super) fn vm(&self) -> &PikeVM<StateId> { &self.engines().vm }
+
+    /// Split `s` by the matches of the pattern, like `str::split`: a match at
+    /// either end or two adjacent matches produce empty pieces.
+    pub fn split<'r, 't>(&'r self, s: &'t str) -> Split<'r, 't> {
+        Split {
+            finder: self.find_iter(s),
+            text: s,
+            last: 0,
+        }
+    }
+
+    /// Split `s` into at most `limit` pieces, the last piece holds the rest
+    /// of the text.
+    pub fn splitn<'r, 't>(&'r self, s: &'t str, limit: usize) -> SplitN<'r, 't> {
+        SplitN {
+            splits: self.split(s),
+            n: limit,
+        }
+    }
+
+    /// Replace the leftmost-first match.
+    pub fn replace<'t, R: Replacer>(&self, s: &'t str, rep: R) -> Cow<'t, str> {
+        self.replacen(s, 1, rep)
+    }
+
+    /// Replace every non-overlapping match.
+    pub fn replace_all<'t, R: Replacer>(&self, s: &'t str, rep: R) -> Cow<'t, str> {
+        self.replacen(s, 0, rep)
+    }
+
+    /// Replace at most `limit` non-overlapping matches, or all of them when
+    /// `limit` is 0. The input is borrowed when nothing matches.
+    pub fn replacen<'t, R: Replacer>(&self, s: &'t str, limit: usize, mut rep: R) -> Cow<'t, str> {
+        let mut it = self.captures_iter(s).enumerate().peekable();
+        if it.peek().is_none() {
+            return Cow::Borrowed(s);
+        }
+        let mut new = String::with_capacity(s.len());
+        let mut last_match = 0;
+        for (i, caps) in it {
+            if limit > 0 && i >= limit {
+                break;
+            }
+            let m = caps.get(0).unwrap();
+            new.push_str(&s[last_match..m.start()]);
+            match rep.no_expansion() {
+                Some(text) => new.push_str(&text),
+                None => rep.replace_append(&caps, &mut new),
+            }
+            last_match = m.end();
+        }
+        new.push_str(&s[last_match..]);
+        Cow::Owned(new)
+    }
+}
+
+/// Iterator over the successive non-overlapping `Captures` in a text.
+pub struct CaptureMatches<'r, 't> {
+    regex: &'r CompiledRegex,
+    text: &'t str,
+    last_end: usize,
+    last_match: Option<usize>,
+}
+
+impl<'r, 't> Iterator for CaptureMatches<'r, 't> {
+    type Item = Captures<'t>;
+
+    fn next(&mut self) -> Option<Captures<'t>> {
+        loop {
+            if self.last_end > self.text.len() {
+                return None;
+            }
+            let caps = self.regex.captures_at(self.text, self.last_end)?;
+            let (start, end) = {
+                let m = caps.get(0).unwrap();
+                (m.start(), m.end())
+            };
+            if start == end {
+                // Step over the next character so an empty match cannot
+                // repeat at the same position.
+                self.last_end = end + self.text[end..].chars().next()
+                                                      .map_or(1, |c| c.len_utf8());
+                if Some(end) == self.last_match {
+                    continue;
+                }
+            } else {
+                self.last_end = end;
+            }
+            self.last_match = Some(end);
+            return Some(caps);
+        }
+    }
+}
+
+/// Iterator over the successive non-overlapping `Match`es in a text.
+pub struct Matches<'r, 't>(CaptureMatches<'r, 't>);
+
+impl<'r, 't> Iterator for Matches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        self.0.next().and_then(|caps| caps.get(0))
+    }
+}
+
+/// Iterator over all the matches in a text, overlapping or not.
+pub struct OverlappingMatches<'r, 't> {
+    text: &'t str,
+    spans: MatchSpans<'r, 't, StateId>,
+}
+
+impl<'r, 't> Iterator for OverlappingMatches<'r, 't> {
+    type Item = Match<'t>;
+
+    fn next(&mut self) -> Option<Match<'t>> {
+        self.spans.next().map(|(start, end)| Match::new(self.text, start, end))
+    }
+}
+
+/// Iterator over the pieces of a text between matches.
+pub struct Split<'r, 't> {
+    finder: Matches<'r, 't>,
+    text: &'t str,
+    last: usize,
+}
+
+impl<'r, 't> Iterator for Split<'r, 't> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        match self.finder.next() {
+            Some(m) => {
+                let piece = &self.text[self.last..m.start()];
+                self.last = m.end();
+                Some(piece)
+            },
+            None => {
+                if self.last > self.text.len() {
+                    None
+                } else {
+                    let piece = &self.text[self.last..];
+                    self.last = self.text.len() + 1;
+                    Some(piece)
+                }
+            },
+        }
+    }
+}
+
+/// Iterator over at most `n` pieces of a text between matches.
+pub struct SplitN<'r, 't> {
+    splits: Split<'r, 't>,
+    n: usize,
+}
+
+impl<'r, 't> Iterator for SplitN<'r, 't> {
+    type Item = &'t str;
+
+    fn next(&mut self) -> Option<&'t str> {
+        if self.n == 0 {
+            return None;
+        }
+        self.n -= 1;
+        if self.n > 0 {
+            return self.splits.next();
+        }
+        let text = self.splits.text;
+        if self.splits.last > text.len() {
+            None
+        } else {
+            Some(&text[self.splits.last..])
+        }
+    }
+}
@@ -0,0 +1,111 @@
+use super::regex::{Regex};
+
+/// Strings found in every match of a pattern, to look for with a substring
+/// search before running the automaton.
+#[derive(Debug,Clone,PartialEq,Eq,Default)]
+pub struct Literals {
+    /// Every match starts with it.
+    pub prefix: String,
+    /// Every match contains it, the longest such string found.
+    pub required: String,
+}
+
+impl Literals {
+    pub fn new(regex: &Regex) -> Self {
+        let info = Info::of(regex);
+        Literals {
+            prefix: info.prefix,
+            required: info.inner,
+        }
+    }
+}
+
+/// What is known of the strings a pattern matches.
+struct Info {
+    /// The only string matched, if there is just one.
+    exact: Option<String>,
+    prefix: String,
+    suffix: String,
+    /// The longest string known to be in every match.
+    inner: String,
+}
+
+impl Info {
+    fn exact(s: String) -> Self {
+        Info { exact: Some(s.clone()), prefix: s.clone(), suffix: s.clone(), inner: s }
+    }
+
+    fn unknown() -> Self {
+        Info { exact: None, prefix: String::new(), suffix: String::new(), inner: String::new() }
+    }
+
+    fn new(prefix: String, suffix: String, inner: String) -> Self {
+        let inner = longest(longest(inner, prefix.clone()), suffix.clone());
+        Info { exact: None, prefix, suffix, inner }
+    }
+
+    fn of(regex: &Regex) -> Self {
+        match *regex {
+            Regex::Empty | Regex::Anchor(_) => Info::exact(String::new()),
+            Regex::Literal(c) => Info::exact(c.to_string()),
+            Regex::Set(ref set, false) if set.len() == 1 => {
+                let (start, end) = set[0].bounds();
+                if start == end { Info::exact(start.to_string()) } else { Info::unknown() }
+            },
+            Regex::Byte(_) | Regex::Set(_, _) | Regex::Any | Regex::AnyButNewline => Info::unknown(),
+            Regex::Concatenate(ref l, ref r) => {
+                let (l, r) = (Info::of(l), Info::of(r));
+                if let (Some(l), Some(r)) = (&l.exact, &r.exact) {
+                    return Info::exact(format!("{}{}", l, r));
+                }
+                let prefix = match l.exact {
+                    Some(ref exact) => format!("{}{}", exact, r.prefix),
+                    None => l.prefix.clone(),
+                };
+                let suffix = match r.exact {
+                    Some(ref exact) => format!("{}{}", l.suffix, exact),
+                    None => r.suffix.clone(),
+                };
+                let junction = format!("{}{}", l.suffix, r.prefix);
+                Info::new(prefix, suffix, longest(longest(l.inner, r.inner), junction))
+            },
+            Regex::Choose(ref l, ref r) => {
+                let (l, r) = (Info::of(l), Info::of(r));
+                if l.exact.is_some() && l.exact == r.exact {
+                    return l;
+                }
+                Info::new(common_prefix(&l.prefix, &r.prefix), common_suffix(&l.suffix, &r.suffix), String::new())
+            },
+            Regex::Repeat(_) | Regex::Optional(_) => Info::unknown(),
+            Regex::Plus(ref p) => {
+                let p = Info::of(p);
+                Info::new(p.prefix, p.suffix, p.inner)
+            },
+            Regex::Group(_, ref p) => Info::of(p),
+        }
+    }
+}
+
+fn longest(a: String, b: String) -> String {
+    if b.len() > a.len() { b } else { a }
+}
+
+fn common_prefix(a: &str, b: &str) -> String {
+    a.chars().zip(b.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a).collect()
+}
+
+fn common_suffix(a: &str, b: &str) -> String {
+    let mut suffix = a.chars().rev().zip(b.chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect::<Vec<char>>();
+    suffix.reverse();
+    suffix.into_iter().collect()
+}
+
+/// Find the byte offset of the first `needle` in `haystack` at or after
+/// `start`.
+pub fn find_literal(haystack: &str, needle: &str, start: usize) -> Option<usize> {
+    haystack[start..].find(needle).map(|i| start + i)
+}
+
@@ -1,12 +1,43 @@
 pub mod regex;
+pub mod ahocorasick;
 pub mod tonfa;
-mod state;
+pub mod bytes;
+pub mod captures;
+pub mod compiled;
+pub mod literals;
+pub mod options;
+pub mod positions;
+pub mod replace;
+pub mod shiftand;
+pub mod state;
+pub mod stream;
+mod pikevm;
+mod utf8;
 
 #[cfg(test)]
 mod tests {
     use finite_automata::faruledata::{FARuleData};
+    use finite_automata::lazydfa::{LazyDFAOptions};
+    use finite_automata::look::{LineTerminator};
+    use finite_automata::nfa::{PrefixStatus};
+    use finite_automata::subset::{SubsetError};
     use super::regex::*;
+    use super::ahocorasick::*;
+    use super::pikevm::*;
+    use super::positions::*;
     use super::tonfa::*;
+    use super::bytes::*;
+    use super::captures::*;
+    use super::compiled::*;
+    use super::literals::*;
+    use super::options::*;
+    use super::replace::*;
+    use super::shiftand::*;
+    use super::state::*;
+
+    fn leftmost_longest() -> CompileOptions {
+        CompileOptions { match_kind: MatchKind::LeftmostLongest, ..CompileOptions::default() }
+    }
 
     #[test]
     fn test_regex_pattern() {
@@ -131,4 +162,651 @@ mod tests {
         assert!(pattern.matches(""));
         assert!(pattern.matches("枯籐老樹昏鴉小橋流水人家古道西風瘦馬夕陽西下斷腸人卻在燈火闌珊處"));
     }
+
+    #[test]
+    fn test_regex_group() {
+        let pattern = Regex::concatenate(Regex::named_group("x", Regex::plus(Regex::literal('a'))),
+                                         Regex::group(Regex::optional(Regex::literal('b'))));
+        assert_eq!("(?P<x>a+)(b?)", format!("{}", pattern));
+        assert_eq!(vec![None, Some("x".to_string()), None], pattern.capture_names());
+        assert!(pattern.matches("aab"));
+        assert!(!pattern.matches("b"));
+    }
+
+    #[test]
+    fn test_compiled_find() {
+        let pattern = CompiledRegex::new(&Regex::plus(Regex::set(&vec![FARuleData::range('0', '9')], false)));
+        assert!(pattern.is_match("abc123"));
+        assert!(!pattern.is_match("abc"));
+        let m = pattern.find("ab12c345").unwrap();
+        assert_eq!((2, 4, "12"), (m.start(), m.end(), m.as_str()));
+        let all = pattern.find_iter("ab12c345").map(|m| m.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["12", "345"], all);
+    }
+
+    #[test]
+    fn test_compiled_leftmost_first() {
+        let pattern = CompiledRegex::new(&Regex::choose(Regex::literal('a'),
+            Regex::concatenate(Regex::literal('a'), Regex::literal('b'))));
+        assert_eq!("a", pattern.find("ab").unwrap().as_str());
+        let pattern = CompiledRegex::new(&Regex::repeat(Regex::literal('a')));
+        assert_eq!("aaa", pattern.find("aaab").unwrap().as_str());
+    }
+
+    #[test]
+    fn test_compiled_empty_matches() {
+        let pattern = CompiledRegex::new(&Regex::repeat(Regex::literal('a')));
+        let all = pattern.find_iter("baa猛").map(|m| (m.start(), m.end())).collect::<Vec<_>>();
+        assert_eq!(vec![(0, 0), (1, 3), (6, 6)], all);
+    }
+
+    #[test]
+    fn test_compiled_captures() {
+        let digits = Regex::plus(Regex::set(&vec![FARuleData::range('0', '9')], false));
+        let pattern = CompiledRegex::new(&Regex::concatenate(
+            Regex::named_group("key", Regex::plus(Regex::set(&vec![FARuleData::range('a', 'z')], false))),
+            Regex::concatenate(Regex::literal('='),
+                Regex::choose(Regex::group(digits), Regex::group(Regex::literal('x'))))));
+        let caps = pattern.captures("-- id=42 --").unwrap();
+        assert_eq!(4, caps.len());
+        assert_eq!("id=42", &caps[0]);
+        assert_eq!("id", &caps["key"]);
+        assert_eq!("42", &caps[2]);
+        assert!(caps.get(3).is_none());
+        assert_eq!(Some(3..5), caps.name("key").map(|m| m.range()));
+    }
+
+    #[test]
+    fn test_compiled_replace() {
+        let digits = Regex::plus(Regex::set(&vec![FARuleData::range('0', '9')], false));
+        let pattern = CompiledRegex::new(&Regex::concatenate(
+            Regex::named_group("user", Regex::plus(Regex::set(&vec![FARuleData::range('a', 'z')], false))),
+            Regex::concatenate(Regex::literal('@'), Regex::group(digits))));
+        let text = "bob@12 and amy@345";
+        assert_eq!("<12:bob> and amy@345", pattern.replace(text, "<$2:${user}>"));
+        assert_eq!("<12:bob> and <345:amy>", pattern.replace_all(text, "<${2}:$user>"));
+        assert_eq!("$ and $", pattern.replace_all(text, "$$"));
+        assert_eq!("a and a", pattern.replace_all(text, NoExpand("a")));
+        assert_eq!("BOB and AMY", pattern.replace_all(text, |caps: &Captures| caps["user"].to_uppercase()));
+        assert_eq!("x and amy@345", pattern.replacen(text, 1, "x"));
+        assert_eq!("x and x", pattern.replacen(text, 0, "x"));
+    }
+
+    #[test]
+    fn test_compiled_replace_no_match_borrows() {
+        use std::borrow::Cow;
+        let pattern = CompiledRegex::new(&Regex::literal('z'));
+        match pattern.replace_all("abc", "y") {
+            Cow::Borrowed(s) => assert_eq!("abc", s),
+            Cow::Owned(_) => panic!("expected a borrowed result"),
+        }
+    }
+
+    #[test]
+    fn test_compiled_split() {
+        let pattern = CompiledRegex::new(&Regex::concatenate(
+            Regex::set(&vec![FARuleData::char(','), FARuleData::char(';')], false),
+            Regex::repeat(Regex::literal(' '))));
+        assert_eq!(vec!["a", "b", "", "c"], pattern.split("a, b;; c").collect::<Vec<_>>());
+        assert_eq!(vec!["", "a", ""], pattern.split(",a;").collect::<Vec<_>>());
+        assert_eq!(vec![""], pattern.split("").collect::<Vec<_>>());
+        assert_eq!(vec!["a", "b;; c"], pattern.splitn("a, b;; c", 2).collect::<Vec<_>>());
+        assert_eq!(vec!["a, b;; c"], pattern.splitn("a, b;; c", 1).collect::<Vec<_>>());
+        assert!(pattern.splitn("a, b", 0).next().is_none());
+        assert_eq!(vec!["a", "b"], pattern.splitn("a, b", 5).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_compiled_overlapping() {
+        let pattern = CompiledRegex::new(&Regex::choose(
+            Regex::concatenate(Regex::literal('a'), Regex::literal('a')),
+            Regex::concatenate(Regex::literal('a'), Regex::concatenate(Regex::literal('b'), Regex::literal('a')))));
+        assert_eq!(vec![2, 3, 5], pattern.match_ends("aaaba").collect::<Vec<_>>());
+        let all = pattern.find_overlapping("aaaba").map(|m| (m.start(), m.end())).collect::<Vec<_>>();
+        assert_eq!(vec![(0, 2), (1, 3), (2, 5)], all);
+
+        let pattern = CompiledRegex::new(&Regex::plus(Regex::literal('a')));
+        let all = pattern.find_overlapping("aaa").map(|m| (m.start(), m.end())).collect::<Vec<_>>();
+        assert_eq!(vec![(0, 1), (0, 2), (1, 2), (0, 3), (1, 3), (2, 3)], all);
+
+        // The same matches as a prefix search from every offset.
+        let word = Regex::concatenate(Regex::anchor(Anchor::StartLine),
+                                      Regex::plus(Regex::choose(Regex::literal('a'), Regex::literal('é'))));
+        for pattern in [Regex::repeat(Regex::literal('a')), word] {
+            let compiled = CompiledRegex::new(&pattern);
+            let design = pattern.to_nfa_design();
+            let text = "aé\naxa";
+            let mut expected = text.char_indices().map(|(i, _)| i).chain(Some(text.len()))
+                .flat_map(|start| design.prefix_match_ends(text, start).map(move |end| (start, end)))
+                .collect::<Vec<_>>();
+            expected.sort_by_key(|&(start, end)| (end, start));
+            let all = compiled.find_overlapping(text).map(|m| (m.start(), m.end())).collect::<Vec<_>>();
+            assert_eq!(expected, all, "{}", pattern);
+        }
+    }
+
+    #[test]
+    fn test_compiled_leftmost_longest() {
+        let pattern = Regex::choose(Regex::group(Regex::literal('a')),
+            Regex::group(Regex::concatenate(Regex::literal('a'), Regex::literal('b'))));
+        let options = CompileOptions { match_kind: MatchKind::LeftmostLongest, ..CompileOptions::default() };
+        let longest = CompiledRegex::with_options(&pattern, &options);
+        let caps = longest.captures("xaby").unwrap();
+        assert_eq!("ab", &caps[0]);
+        assert!(caps.get(1).is_none());
+        assert_eq!("ab", &caps[2]);
+        assert_eq!("a", CompiledRegex::new(&pattern).find("xaby").unwrap().as_str());
+
+        let pattern = Regex::concatenate(Regex::plus(Regex::literal('a')),
+            Regex::optional(Regex::concatenate(Regex::literal('b'), Regex::literal('c'))));
+        let longest = CompiledRegex::with_options(&pattern, &options);
+        let all = longest.find_iter("aab aabc").map(|m| m.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["aa", "aabc"], all);
+
+        // The match starting at 0 ends after the one starting at 2.
+        let pattern = Regex::choose(Regex::literal_string("abcd"), Regex::literal('c'));
+        let vm = PikeVM::new(&pattern.to_nfa_design(), 2, MatchKind::LeftmostLongest);
+        assert_eq!(Some(vec![Some(0), Some(4)]), vm.search("abcd", 0));
+        assert_eq!(Some(0..4), CompiledRegex::with_options(&pattern, &options).find("abcd").map(|m| m.range()));
+    }
+
+    #[test]
+    fn test_compiled_shortest_match() {
+        let pattern = CompiledRegex::new(&Regex::plus(Regex::literal('a')));
+        assert_eq!(Some(2), pattern.shortest_match("xaaa"));
+        assert_eq!(None, pattern.shortest_match("xyz"));
+    }
+
+    #[test]
+    fn test_stream_matcher() {
+        let pattern = CompiledRegex::new(&Regex::choose(
+            Regex::concatenate(Regex::literal('a'), Regex::plus(Regex::literal('猛'))),
+            Regex::literal('b')));
+        let text = "xa猛猛b a猛ab";
+        let expected = pattern.find_iter(text).map(|m| m.range()).collect::<Vec<_>>();
+        assert_eq!(4, expected.len());
+        for size in 1..text.len() + 1 {
+            let mut matcher = pattern.stream_matcher();
+            let mut found = Vec::new();
+            for chunk in text.as_bytes().chunks(size) {
+                found.extend(matcher.feed(chunk).unwrap());
+            }
+            found.extend(matcher.finish().unwrap());
+            assert_eq!(expected, found);
+        }
+    }
+
+    #[test]
+    fn test_stream_matcher_empty_and_longest() {
+        let pattern = Regex::repeat(Regex::literal('a'));
+        let compiled = CompiledRegex::new(&pattern);
+        let expected = compiled.find_iter("baab").map(|m| m.range()).collect::<Vec<_>>();
+        let mut matcher = compiled.stream_matcher();
+        let mut found = matcher.feed(b"ba").unwrap();
+        found.extend(matcher.feed(b"ab").unwrap());
+        found.extend(matcher.finish().unwrap());
+        assert_eq!(expected, found);
+
+        let pattern = Regex::choose(Regex::literal('a'),
+            Regex::concatenate(Regex::literal('a'), Regex::concatenate(Regex::literal('b'), Regex::literal('c'))));
+        let options = CompileOptions { match_kind: MatchKind::LeftmostLongest, ..CompileOptions::default() };
+        let compiled = CompiledRegex::with_options(&pattern, &options);
+        let found = compiled.find_iter_read("abcabdaa".as_bytes())
+                            .collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(vec![0..3, 3..4, 6..7, 7..8], found);
+    }
+
+    #[test]
+    fn test_stream_matcher_invalid_utf8() {
+        let pattern = CompiledRegex::new(&Regex::literal('a'));
+        let mut matcher = pattern.stream_matcher();
+        assert_eq!(vec![0..1], matcher.feed(&[b'a', b'b', 0xe7]).unwrap());
+        assert!(matcher.finish().is_err());
+        assert!(pattern.stream_matcher().feed(&[0xff]).is_err());
+    }
+
+    #[test]
+    fn test_regex_nfa_snapshot() {
+        let pattern = Regex::concatenate(Regex::plus(Regex::choose(Regex::literal('a'), Regex::literal('b'))),
+                                         Regex::literal('c'));
+        let design = pattern.to_nfa_design();
+        let mut nfa = design.to_nfa();
+        nfa.read_string("abba");
+        let saved = nfa.snapshot(&design.state_ids()).unwrap().to_string();
+
+        // A design built again has new states but the same ids.
+        let rebuilt = pattern.to_nfa_design();
+        let ids = rebuilt.state_ids();
+        assert_eq!(design.state_ids().fingerprint(), ids.fingerprint());
+        let mut resumed = rebuilt.to_nfa();
+        resumed.restore(&ids, &saved.parse().unwrap()).unwrap();
+        resumed.read_character('c');
+        assert!(resumed.accepting());
+    }
+
+    #[test]
+    fn test_regex_minimize() {
+        let pattern = Regex::choose(
+            Regex::choose(
+                Regex::concatenate(Regex::literal_string("ab"),
+                                   Regex::concatenate(Regex::repeat(Regex::literal('a')), Regex::repeat(Regex::literal('c')))),
+                Regex::literal_string("caa")),
+            Regex::repeat(Regex::literal('b')));
+        let nfa_design = pattern.to_nfa_design();
+        let minimal = nfa_design.determinize().unwrap().minimize();
+        let mut inputs = vec![String::new()];
+        for _ in 0..5 {
+            inputs = inputs.iter().flat_map(|s| "abc".chars().map(move |c| format!("{}{}", s, c))).collect();
+            for s in inputs.iter() {
+                assert_eq!(nfa_design.accept(s), minimal.accept(s), "{:?}", s);
+            }
+        }
+    }
+
+    #[test]
+    fn test_regex_byte() {
+        let pattern = Regex::concatenate(Regex::byte(0x41), Regex::byte(0xff));
+        assert_eq!("\\x41\\xFF", format!("{}", pattern));
+        assert!(pattern.matches("Aÿ"));
+    }
+
+    #[test]
+    fn test_bytes_regex() {
+        let pattern = BytesRegex::new(&Regex::concatenate(Regex::byte(0xff),
+            Regex::concatenate(Regex::any(), Regex::literal('猛'))));
+        let haystack = b"\x00\xff\xce\xbb\xe7\x8c\x9b\xff";
+        let m = pattern.find(haystack).unwrap();
+        assert_eq!(1..7, m.range());
+        assert!(!pattern.is_match(b"\xff\xce\xe7\x8c\x9b"));
+
+        let latin = BytesRegex::new(&Regex::plus(Regex::set(&vec![FARuleData::range('a', 'z')], true)));
+        let found = latin.find_iter(b"ab\xe9\xe8c\xce\xbbd").map(|m| m.as_bytes()).collect::<Vec<_>>();
+        assert_eq!(vec![&b"\xce\xbb"[..]], found);
+    }
+
+    #[test]
+    fn test_bytes_regex_unicode_ranges() {
+        let set = vec![FARuleData::range('\u{7f}', '\u{10ffff}')];
+        let pattern = BytesRegex::new(&Regex::set(&set, false));
+        for &c in &['\u{7f}', '\u{80}', '\u{7ff}', '\u{800}', '\u{d7ff}', '\u{e000}',
+                    '\u{ffff}', '\u{10000}', '\u{10ffff}'] {
+            let mut buffer = [0; 4];
+            let encoded = c.encode_utf8(&mut buffer).as_bytes();
+            assert_eq!(Some(0..encoded.len()), pattern.find(encoded).map(|m| m.range()));
+        }
+        assert!(!pattern.is_match(b"a"));
+        assert!(!pattern.is_match(b"\xed\xa0\x80"));
+    }
+
+    #[test]
+    fn test_compiled_anchors() {
+        let line = Regex::concatenate(Regex::anchor(Anchor::StartLine),
+            Regex::concatenate(Regex::plus(Regex::any_but_newline()), Regex::anchor(Anchor::EndLine)));
+        let pattern = CompiledRegex::new(&line);
+        let lines = pattern.find_iter("ab\ncd\n\nef").map(|m| m.as_str()).collect::<Vec<_>>();
+        assert_eq!(vec!["ab", "cd", "ef"], lines);
+        assert!(line.matches("ab"));
+        assert!(!line.matches("ab\ncd"));
+
+        let text = Regex::concatenate(Regex::anchor(Anchor::StartText),
+            Regex::concatenate(Regex::repeat(Regex::any()), Regex::anchor(Anchor::EndText)));
+        let pattern = CompiledRegex::new(&text);
+        assert_eq!(Some((0, 5)), pattern.find("ab\ncd").map(|m| (m.start(), m.end())));
+        assert_eq!(None, pattern.find_at("ab\ncd", 1));
+
+        let start = CompiledRegex::new(&Regex::concatenate(Regex::anchor(Anchor::StartText), Regex::literal('a')));
+        assert_eq!(1, start.find_iter("aaa").count());
+        assert_eq!(1, start.find_overlapping("aaa").count());
+        assert_eq!(Some(1), start.shortest_match("aaa"));
+    }
+
+    #[test]
+    fn test_nfa_completions_anchors() {
+        // Every way of picking the characters of a completion is accepted,
+        // and no shorter input over the alphabet is.
+        let alphabet = ['a', 'b', '\r', '\n'];
+        let line = |anchor| Regex::anchor(anchor);
+        let patterns = vec![
+            Regex::concatenate(Regex::choose(line(Anchor::StartLine), Regex::literal('a')),
+                               Regex::concatenate(Regex::literal('b'), line(Anchor::EndLine))),
+            Regex::concatenate(Regex::repeat(Regex::any()), Regex::concatenate(line(Anchor::StartLine), Regex::literal('b'))),
+            Regex::concatenate(Regex::literal('a'), Regex::concatenate(line(Anchor::EndLine),
+                Regex::concatenate(Regex::any(), Regex::concatenate(line(Anchor::StartLine), Regex::literal('b'))))),
+            Regex::concatenate(line(Anchor::StartText), Regex::concatenate(
+                Regex::repeat(Regex::choose(Regex::literal('a'), Regex::any_but_newline())), line(Anchor::EndText))),
+        ];
+        let mut inputs = vec![String::new()];
+        for length in 1..5 {
+            let shorter = inputs.iter().filter(|input| input.chars().count() == length - 1).cloned().collect::<Vec<_>>();
+            for input in shorter {
+                inputs.extend(alphabet.iter().map(|&c| format!("{}{}", input, c)));
+            }
+        }
+        for pattern in &patterns {
+            for &terminator in &[LineTerminator::LF, LineTerminator::CRLF] {
+                let design = pattern.to_nfa_design_with(terminator);
+                for prefix in &["", "a", "\r", "\n", "a\r"] {
+                    let mut nfa = design.to_nfa();
+                    nfa.read_string(prefix);
+                    let completions = nfa.completions(3);
+                    let next = nfa.next_characters();
+                    for c in &alphabet {
+                        let live = design.prefix_status(&format!("{}{}", prefix, c)) != PrefixStatus::Dead;
+                        assert_eq!(live, next.iter().any(|data| data.applies_to(c)), "{} {:?} {:?}", pattern, prefix, c);
+                    }
+                    for completion in &completions {
+                        let mut picks = vec![String::new()];
+                        for class in completion {
+                            let candidates = class.iter()
+                                .flat_map(|data| { let (start, end) = data.bounds(); vec![start, end] })
+                                .chain(alphabet.iter().cloned().filter(|c| class.iter().any(|data| data.applies_to(c))))
+                                .collect::<Vec<char>>();
+                            picks = picks.iter()
+                                .flat_map(|pick| candidates.iter().map(move |&c| format!("{}{}", pick, c)))
+                                .collect();
+                        }
+                        for pick in picks {
+                            assert!(design.accept(&format!("{}{}", prefix, pick)), "{} {:?} {:?}", pattern, prefix, pick);
+                        }
+                    }
+                    let shortest = inputs.iter()
+                        .filter(|input| design.accept(&format!("{}{}", prefix, input)))
+                        .map(|input| input.chars().count())
+                        .min();
+                    assert_eq!(shortest, completions.first().map(|completion| completion.len()),
+                               "{} {:?}", pattern, prefix);
+                    assert!(completions.windows(2).all(|pair| pair[0].len() <= pair[1].len()));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_nfa_anchors() {
+        let start = Regex::concatenate(Regex::anchor(Anchor::StartText), Regex::literal('a')).to_nfa_design();
+        assert!(start.accept("a"));
+        assert!(start.accept_symbols("a".chars()));
+        assert_eq!(Some(1), start.shortest_match("ab"));
+        assert_eq!(Some(1), start.longest_prefix_match("aa"));
+        assert_eq!(PrefixStatus::CouldMatch, start.prefix_status(""));
+        assert_eq!(PrefixStatus::Matches, start.prefix_status("a"));
+        assert_eq!(PrefixStatus::Dead, start.prefix_status("b"));
+        assert_eq!(vec![FARuleData::char('a')], start.to_nfa().next_characters());
+        assert_eq!(Some(SubsetError::LookRules), start.to_dfa_design().err());
+        assert_eq!(Some(SubsetError::LookRules), start.determinize().err());
+        assert_eq!(Some(SubsetError::LookRules), start.to_lazy_dfa(LazyDFAOptions::default()).err());
+        assert_eq!(Some(SubsetError::LookRules), start.to_dense_dfa(false).err());
+
+        let end = Regex::concatenate(Regex::plus(Regex::literal('a')), Regex::anchor(Anchor::EndText)).to_nfa_design();
+        assert_eq!(None, end.shortest_match("aab"));
+        assert_eq!(Some(2), end.shortest_match("aa"));
+        assert_eq!(Some(3), end.longest_prefix_match("aaa"));
+        assert_eq!(None, end.longest_prefix_match("aab"));
+        let mut nfa = end.to_nfa();
+        nfa.read_string("aa");
+        assert!(nfa.accepting());
+        assert_eq!(vec![Vec::<Vec<FARuleData>>::new(), vec![vec![FARuleData::char('a')]]],
+                   nfa.completions(2));
+
+        // Any character, then b at the start of a line.
+        let line = Regex::concatenate(Regex::any(), Regex::concatenate(Regex::anchor(Anchor::StartLine), Regex::literal('b')))
+            .to_nfa_design();
+        let mut nfa = line.to_nfa();
+        nfa.read_string("\n");
+        assert_eq!(Some(1), nfa.shortest_match("b"));
+        assert_eq!(None, line.shortest_match("ab"));
+        assert!(line.accept_symbols("\nb".chars()));
+        assert!(!line.accept_symbols("ab".chars()));
+        let completions = line.to_nfa().completions(2);
+        assert_eq!(vec![vec![vec![FARuleData::char('\n')], vec![FARuleData::char('b')]]], completions);
+
+        // A restored NFA is still past the start of the input.
+        let design = Regex::concatenate(Regex::literal('x'),
+                                        Regex::concatenate(Regex::anchor(Anchor::StartText), Regex::literal('b')))
+            .to_nfa_design();
+        let ids = design.state_ids();
+        let mut nfa = design.to_nfa();
+        nfa.read_string("x");
+        let snapshot = nfa.snapshot(&ids).unwrap();
+        assert_eq!(Ok(snapshot.clone()), snapshot.to_string().parse());
+        let mut resumed = design.to_nfa();
+        resumed.restore(&ids, &snapshot).unwrap();
+        resumed.read_string("b");
+        nfa.read_string("b");
+        assert!(!design.accept("xb"));
+        assert!(!nfa.accepting());
+        assert!(!resumed.accepting());
+    }
+
+    #[test]
+    fn test_compiled_crlf() {
+        let line = Regex::concatenate(Regex::anchor(Anchor::StartLine),
+            Regex::concatenate(Regex::repeat(Regex::any_but_newline()), Regex::anchor(Anchor::EndLine)));
+        let text = "ab\r\ncd\r\n";
+        let lf = CompiledRegex::new(&line);
+        assert_eq!(vec!["ab\r", "cd\r", ""], lf.find_iter(text).map(|m| m.as_str()).collect::<Vec<_>>());
+
+        let options = CompileOptions { line_terminator: LineTerminator::CRLF, ..CompileOptions::default() };
+        let crlf = CompiledRegex::with_options(&line, &options);
+        assert_eq!(vec!["ab", "cd", ""], crlf.find_iter(text).map(|m| m.as_str()).collect::<Vec<_>>());
+        // No line boundary between the \r and the \n.
+        assert!(crlf.find_iter(text).all(|m| m.start() != 3));
+
+        let mut matcher = crlf.stream_matcher();
+        let mut found = Vec::new();
+        for chunk in text.as_bytes().chunks(1) {
+            found.extend(matcher.feed(chunk).unwrap());
+        }
+        found.extend(matcher.finish().unwrap());
+        assert_eq!(crlf.find_iter(text).map(|m| m.range()).collect::<Vec<_>>(), found);
+
+        let bytes = BytesRegex::with_options(&line, &options);
+        assert_eq!(vec![&b"ab"[..], b"cd", b""],
+                   bytes.find_iter(text.as_bytes()).map(|m| m.as_bytes()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_regex_state_arena() {
+        let pattern = Regex::concatenate(Regex::literal('a'), Regex::repeat(Regex::literal('b')));
+        let design = pattern.to_nfa_design();
+        let rules = design.rules().iter().map(|rule| rule.to_string()).collect::<Vec<_>>();
+        assert_eq!(vec!["FARule 0 --a--> 1", "FARule 2 --b--> 3", "FARule 3 --free--> 2",
+                        "FARule 4 --free--> 2", "FARule 1 --free--> 4"], rules);
+        assert_eq!(StateId(0), design.start_state());
+        let rebuilt = pattern.to_nfa_design().rules().iter().map(|rule| rule.to_string()).collect::<Vec<_>>();
+        assert_eq!(rules, rebuilt);
+
+        fn assert_send_sync<T: Send + Sync>(_: &T) {}
+        assert_send_sync(&design);
+        assert_send_sync(&CompiledRegex::new(&pattern));
+    }
+
+    #[test]
+    fn test_compiled_is_match_dfa() {
+        let digits = Regex::plus(Regex::set(&vec![FARuleData::range('0', '9')], false));
+        let patterns = [
+            Regex::concatenate(Regex::literal('a'), Regex::concatenate(Regex::any(), digits.clone())),
+            Regex::choose(Regex::literal('é'), Regex::set(&vec![FARuleData::range('a', 'z')], true)),
+            Regex::concatenate(Regex::anchor(Anchor::StartText), digits.clone()),
+            Regex::optional(Regex::literal('x')),
+            // Too many states for the DFA.
+            Regex::concatenate(Regex::repeat(Regex::any()),
+                Regex::concatenate(Regex::literal('a'), (0..12).fold(Regex::empty(), |r, _| {
+                    Regex::concatenate(r, Regex::any())
+                }))),
+        ];
+        let texts = ["", "a", "ab12", "xa-7y", "é", "abc", "12", "x12", "aaaaaaaaaaaaa", "ba1234567890123"];
+        for pattern in patterns.iter() {
+            let compiled = CompiledRegex::new(pattern);
+            for text in texts.iter() {
+                assert_eq!(compiled.find(text).is_some(), compiled.is_match(text), "{} on {:?}", pattern, text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_literal_prefilter() {
+        let literal = |s: &str| s.chars().fold(Regex::empty(), |r, c| Regex::concatenate(r, Regex::literal(c)));
+        let digits = Regex::plus(Regex::set(&vec![FARuleData::range('0', '9')], false));
+
+        let error = Regex::concatenate(literal("ERROR: "), digits.clone());
+        let literals = Literals::new(&error);
+        assert_eq!(("ERROR: ", "ERROR: "), (literals.prefix.as_str(), literals.required.as_str()));
+        let pattern = CompiledRegex::new(&error);
+        let text = "ERROR: 12 ERROR: x ERROR: 345";
+        assert_eq!(vec!["ERROR: 12", "ERROR: 345"], pattern.find_iter(text).map(|m| m.as_str()).collect::<Vec<_>>());
+        assert!(!pattern.is_match("error: 12"));
+        assert_eq!(None, pattern.shortest_match("no ERROR here"));
+
+        let inner = Regex::concatenate(Regex::repeat(Regex::choose(Regex::literal('a'), Regex::literal('b'))),
+                                       Regex::concatenate(literal("xyz"), digits.clone()));
+        assert_eq!(Literals { prefix: String::new(), required: "xyz".to_string() }, Literals::new(&inner));
+        let pattern = CompiledRegex::new(&inner);
+        assert_eq!(Some("abxyz1"), pattern.find("--abxyz1").map(|m| m.as_str()));
+        assert!(!pattern.is_match("abxy1"));
+
+        let choice = Regex::concatenate(Regex::plus(Regex::choose(literal("foo"), literal("fab"))), literal("bar"));
+        assert_eq!(Literals { prefix: "f".to_string(), required: "bar".to_string() }, Literals::new(&choice));
+        let pattern = CompiledRegex::new(&choice);
+        assert_eq!(vec!["foofabbar", "fabbar"],
+                   pattern.find_iter("ffoofabbar fbar fabbar").map(|m| m.as_str()).collect::<Vec<_>>());
+        assert_eq!(Some((11, 17)), pattern.find_at("ffoofabbar fabbar", 5).map(|m| (m.start(), m.end())));
+
+        let optional = Regex::concatenate(Regex::optional(literal("ab")), literal("c"));
+        assert_eq!(Literals { prefix: String::new(), required: "c".to_string() }, Literals::new(&optional));
+        let anchored = Regex::concatenate(Regex::anchor(Anchor::StartLine), literal("ab"));
+        let pattern = CompiledRegex::new(&anchored);
+        assert_eq!(Some(3), pattern.find_at("ab\nab", 1).map(|m| m.start()));
+        assert_eq!(None, pattern.find_at("ab\n-ab", 1));
+    }
+
+    #[test]
+    fn test_aho_corasick() {
+        let words = ["he", "she", "his", "hers", "h", "sh"];
+        let text = "ushers shis hishe";
+        let branches = words.iter().map(|word| Regex::literal_string(word)).collect::<Vec<_>>();
+        let pattern = Regex::alternation(branches);
+        assert_eq!(Some(words.iter().map(|word| word.to_string()).collect()), pattern.literal_alternatives());
+        for &match_kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest].iter() {
+            let options = CompileOptions { match_kind, ..CompileOptions::default() };
+            let searcher = AhoCorasick::new(&words, match_kind);
+            let vm = PikeVM::new(&pattern.to_nfa_design(), 2, match_kind);
+            let mut start = 0;
+            while start <= text.len() {
+                let found = searcher.find_at(text, start).map(|(start, end, _)| (start, end));
+                assert_eq!(vm.search(text, start).map(|slots| (slots[0].unwrap(), slots[1].unwrap())), found);
+                start += 1;
+            }
+            let compiled = CompiledRegex::from_literals(&words, &options);
+            assert_eq!(compiled.find_iter(text).map(|m| m.range()).collect::<Vec<_>>(),
+                       CompiledRegex::with_options(&Regex::concatenate(Regex::empty(), pattern.clone()), &options)
+                           .find_iter(text).map(|m| m.range()).collect::<Vec<_>>());
+        }
+        let searcher = AhoCorasick::new(&["é", "x"], MatchKind::LeftmostFirst);
+        assert_eq!(Some((2, 4, 0)), searcher.find_at("éé x", 1));
+        assert_eq!(Some((5, 6, 1)), searcher.find_at("éé x", 3));
+        assert_eq!(None, searcher.find_at("é", 1));
+        assert_eq!(None, searcher.find_at("é", 3));
+        let accents = CompiledRegex::from_literals(&["é", "x"], &CompileOptions::default());
+        assert_eq!(Some(2..4), accents.find_at("éé", 1).map(|m| m.range()));
+
+        let first = CompiledRegex::from_literals(&["sh", "she", "shers"], &CompileOptions::default());
+        assert_eq!(vec!["sh", "sh"], first.find_iter("ushers shis").map(|m| m.as_str()).collect::<Vec<_>>());
+        let options = leftmost_longest();
+        let longest = CompiledRegex::from_literals(&["sh", "she", "shers"], &options);
+        assert_eq!(vec!["shers", "sh"], longest.find_iter("ushers shis").map(|m| m.as_str()).collect::<Vec<_>>());
+
+        let blocklist = (0..2000).map(|i| format!("word{}", i)).collect::<Vec<String>>();
+        let pattern = CompiledRegex::from_literals(&blocklist, &CompileOptions::default());
+        assert!(pattern.is_match("a word1999 here"));
+        assert!(!pattern.is_match("a word here"));
+        // word1 comes before word123 in the alternation.
+        assert_eq!(Some("word1"), pattern.find("xword123x").map(|m| m.as_str()));
+        assert_eq!(None, Regex::concatenate(Regex::literal('a'), Regex::any()).literal_alternatives());
+    }
+
+    #[test]
+    fn test_shift_and() {
+        let digit = Regex::set(&vec![FARuleData::range('0', '9')], false);
+        let patterns = [
+            Regex::concatenate(Regex::repeat(Regex::choose(Regex::literal('a'), Regex::literal('b'))), Regex::literal_string("abb")),
+            Regex::concatenate(Regex::plus(digit.clone()),
+                               Regex::optional(Regex::concatenate(Regex::literal('.'), Regex::plus(digit.clone())))),
+            Regex::concatenate(Regex::literal('x'), Regex::concatenate(Regex::any_but_newline(), Regex::literal('y'))),
+            Regex::concatenate(Regex::set(&vec![FARuleData::char('q')], true), Regex::concatenate(Regex::any(), Regex::literal('z'))),
+            Regex::group(Regex::optional(Regex::literal('a'))),
+            Regex::concatenate(Regex::plus(Regex::literal('a')), Regex::literal_string("bbabbabbab")),
+        ];
+        let texts = ["", "abb", "aabbabb", "ab", "12.5", "1.", ".5", "x\ny", "xzy", "qqz", "a\nz", "zz", "b",
+                     "abbabbabbab", "xaabbabbabbabx", "abbabbabba"];
+        for pattern in patterns.iter() {
+            let positions = Positions::new(pattern, LineTerminator::LF).unwrap();
+            let shift_and = ShiftAnd::new(&positions).unwrap();
+            let design = pattern.to_nfa_design();
+            for text in texts.iter() {
+                assert_eq!(design.accept(text), shift_and.accept(text), "{} on {:?}", pattern, text);
+                assert_eq!(design.match_ends(text).next(), shift_and.shortest_match(text), "{} on {:?}", pattern, text);
+            }
+        }
+
+        let positions = Positions::new(&patterns[0], LineTerminator::LF).unwrap();
+        assert_eq!(5, positions.len());
+        assert_eq!(vec![0, 1, 2], positions.first);
+        assert_eq!(vec![0, 1, 2], positions.follow[0]);
+        assert_eq!(vec![4], positions.last);
+        assert!(Positions::new(&Regex::concatenate(Regex::anchor(Anchor::StartText), Regex::literal('a')), LineTerminator::LF).is_none());
+        let long = Regex::concatenate(Regex::literal_string(&"a".repeat(MAX_POSITIONS)), Regex::literal('b'));
+        assert!(ShiftAnd::new(&Positions::new(&long, LineTerminator::LF).unwrap()).is_none());
+        assert!(CompiledRegex::new(&long).is_match(&format!("-{}b", "a".repeat(MAX_POSITIONS))));
+
+        let pattern = CompiledRegex::new(&patterns[1]);
+        assert!(pattern.is_match("v2.10"));
+        assert!(!pattern.is_match("v.x"));
+        assert_eq!(Some(2), pattern.shortest_match("v2.10"));
+    }
+
+    #[test]
+    fn test_glushkov() {
+        let patterns = [
+            Regex::concatenate(Regex::repeat(Regex::choose(Regex::literal('a'), Regex::literal('b'))), Regex::literal_string("abb")),
+            Regex::choose(Regex::literal_string("a"), Regex::literal_string("ab")),
+            Regex::concatenate(Regex::plus(Regex::concatenate(Regex::literal('x'), Regex::optional(Regex::literal('y')))),
+                               Regex::optional(Regex::literal('y'))),
+            Regex::concatenate(Regex::set(&vec![FARuleData::range('0', '9')], true), Regex::any_but_newline()),
+            Regex::optional(Regex::plus(Regex::literal('a'))),
+        ];
+        let texts = ["", "abb", "aabbabb", "ab", "xyxy", "xyy", "xx", "a1\n", "12a", "aaa"];
+        for pattern in patterns.iter() {
+            let design = pattern.to_glushkov_nfa_design_with(LineTerminator::LF).unwrap();
+            let positions = Positions::new(pattern, LineTerminator::LF).unwrap();
+            assert!(design.rules().iter().all(|rule| rule.guard().is_some()));
+            let mut states = design.rules().iter().flat_map(|rule| vec![rule.state, rule.next_state]).collect::<Vec<_>>();
+            states.push(design.start_state());
+            states.sort();
+            states.dedup();
+            assert_eq!(positions.len() + 1, states.len());
+            let thompson = pattern.to_nfa_design();
+            for &match_kind in [MatchKind::LeftmostFirst, MatchKind::LeftmostLongest].iter() {
+                let options = CompileOptions { match_kind, ..CompileOptions::default() };
+                let glushkov = CompiledRegex::with_options(
+                    pattern, &CompileOptions { construction: Construction::Glushkov, ..options.clone() });
+                assert_eq!(Construction::Glushkov, glushkov.construction());
+                let expected = CompiledRegex::with_options(pattern, &options);
+                assert_eq!(Construction::Thompson, expected.construction());
+                for text in texts.iter() {
+                    assert_eq!(thompson.accept(text), design.accept(text), "{} on {:?}", pattern, text);
+                    assert_eq!(expected.find_iter(text).map(|m| m.range()).collect::<Vec<_>>(),
+                               glushkov.find_iter(text).map(|m| m.range()).collect::<Vec<_>>(), "{} on {:?}", pattern, text);
+                }
+            }
+        }
+
+        let anchored = Regex::concatenate(Regex::anchor(Anchor::EndText), Regex::literal('a'));
+        assert!(anchored.to_glushkov_nfa_design_with(LineTerminator::LF).is_none());
+        let options = CompileOptions { construction: Construction::Glushkov, ..CompileOptions::default() };
+        assert_eq!(Construction::Thompson, CompiledRegex::with_options(&anchored, &options).construction());
+        let grouped = CompiledRegex::with_options(&Regex::concatenate(Regex::group(Regex::literal('a')), Regex::literal('b')), &options);
+        assert_eq!(Construction::Thompson, grouped.construction());
+        assert_eq!(Some((0, 1)), grouped.captures("ab").and_then(|caps| caps.get(1)).map(|m| (m.start(), m.end())));
+    }
 }
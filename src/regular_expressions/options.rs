@@ -0,0 +1,43 @@
+use finite_automata::look::{LineTerminator};
+
+/// Which match to report when several matches start at the same, leftmost
+/// position.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum MatchKind {
+    /// Prefer earlier alternatives and greedy repetition, like Perl.
+    /// `a|ab` finds `a` in "ab".
+    LeftmostFirst,
+    /// Prefer the longest match, like POSIX. `a|ab` finds `ab` in "ab".
+    LeftmostLongest,
+}
+
+/// How a `Regex` is turned into an automaton.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Construction {
+    /// Thompson construction, handling every pattern but full of free
+    /// moves.
+    Thompson,
+    /// Glushkov construction, with a state per position of the pattern and
+    /// no free moves. Patterns with capture groups or anchors fall back to
+    /// Thompson, see `CompiledRegex::construction`.
+    Glushkov,
+}
+
+/// Options used when compiling a `Regex` into a `CompiledRegex`.
+#[derive(Debug,Clone)]
+pub struct CompileOptions {
+    pub match_kind: MatchKind,
+    /// What `.` does not match and where the line anchors match.
+    pub line_terminator: LineTerminator,
+    pub construction: Construction,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        CompileOptions {
+            match_kind: MatchKind::LeftmostFirst,
+            line_terminator: LineTerminator::LF,
+            construction: Construction::Thompson,
+        }
+    }
+}
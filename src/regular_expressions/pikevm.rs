@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::hash::Hash;
+
+use finite_automata::classes::{SymbolClasses};
+use finite_automata::farule::{FARule};
+use finite_automata::nfadesign::{NFADesign};
+use super::literals::{find_literal};
+use super::options::{MatchKind};
+
+pub type Slots = Vec<Option<usize>>;
+
+/// A rule with, for every symbol class, whether it reads it.
+type Transition<T> = (FARule<T>, Vec<bool>);
+
+enum Thread<T> {
+    Step(T, Slots),
+    Match(Slots),
+}
+
+/// The threads of a search in progress, see `PikeVM::step`. The free
+/// moves of the threads are only followed once the character after them is
+/// known, as `Look` rules depend on it.
+pub struct Search<T> {
+    clist: Vec<(T, Slots)>,
+    prev: Option<char>,
+    pos: usize,
+    matched: Option<Slots>,
+}
+
+impl<T> Search<T> {
+    /// The byte offset of the next character to read.
+    pub fn pos(&self) -> usize { self.pos }
+    /// The character before `pos`, `None` at the start of the input.
+    pub fn prev(&self) -> Option<char> { self.prev }
+    /// The best match found so far.
+    pub fn matched(&self) -> Option<&Slots> { self.matched.as_ref() }
+}
+
+/// Simulates an `NFADesign` one thread per state, keeping the threads in
+/// priority order so the leftmost-first match and its capture tags can be
+/// reported. The outgoing rules of every state are tried in the order the
+/// rulebook lists them. Threads started earlier always come first, so for
+/// leftmost-longest the threads are kept running until every thread with
+/// the same start as the match has died. Each character read is looked up
+/// in the symbol classes of the design once, and every rule knows the
+/// classes it reads.
+#[derive(Clone)]
+pub struct PikeVM<T> {
+    start_state: T,
+    accept_states: HashSet<T>,
+    transitions: HashMap<T, Vec<Transition<T>>>,
+    classes: SymbolClasses,
+    /// A string every match starts with, `search` skips to it whenever no
+    /// thread is running.
+    prefix: String,
+    slots: usize,
+    kind: MatchKind,
+}
+
+impl<T: Eq + Clone + Hash> PikeVM<T> {
+    pub fn new(design: &NFADesign<T>, slots: usize, kind: MatchKind) -> Self {
+        let classes = design.symbol_classes();
+        let mut transitions: HashMap<T, Vec<Transition<T>>> = HashMap::new();
+        for rule in design.rules() {
+            let reads = (0..classes.len())
+                .map(|class| rule.applies_to(&rule.state, Some(classes.representative(class))))
+                .collect();
+            transitions.entry(rule.state.clone()).or_default().push((rule, reads));
+        }
+        PikeVM {
+            start_state: design.start_state(),
+            accept_states: design.accept_state(),
+            transitions,
+            classes,
+            prefix: String::new(),
+            slots,
+            kind,
+        }
+    }
+
+    /// The same machine, told that every match starts with `prefix`.
+    pub fn with_prefix(mut self, prefix: &str) -> Self {
+        self.prefix = prefix.to_string();
+        self
+    }
+
+    /// Search `s` for the leftmost match starting at or after byte offset
+    /// `start`, returning the capture slots of the match.
+    pub fn search(&self, s: &str, start: usize) -> Option<Slots> {
+        let mut search = self.start(start, s[..start].chars().next_back());
+        loop {
+            if search.clist.is_empty() && search.matched.is_none() && !self.prefix.is_empty() {
+                let pos = find_literal(s, &self.prefix, search.pos)?;
+                search = self.start(pos, s[..pos].chars().next_back());
+            }
+            let c = s[search.pos..].chars().next();
+            if !self.step(&mut search, c) {
+                return search.matched;
+            }
+        }
+    }
+
+    /// Search bytes, each byte `b` read as the character `b as char`.
+    pub fn search_bytes(&self, s: &[u8], start: usize) -> Option<Slots> {
+        let prev = if start > 0 { Some(s[start - 1] as char) } else { None };
+        let mut search = self.start(start, prev);
+        let mut bytes = s[start..].iter();
+        while self.step_byte(&mut search, bytes.next().cloned()) {}
+        search.matched
+    }
+
+    /// A search that has not read anything yet, starting at `pos` right
+    /// after the character `prev`.
+    pub fn start(&self, pos: usize, prev: Option<char>) -> Search<T> {
+        Search {
+            clist: Vec::new(),
+            prev,
+            pos,
+            matched: None,
+        }
+    }
+
+    /// Read the character at `search.pos`, or `None` at the end of the
+    /// input. Returns false once the search is over, in which case `c` was
+    /// left unread.
+    pub fn step(&self, search: &mut Search<T>, c: Option<char>) -> bool {
+        self.step_over(search, c, c.map_or(0, |c| c.len_utf8()))
+    }
+
+    /// Like `step`, for a byte that is one position long.
+    pub fn step_byte(&self, search: &mut Search<T>, b: Option<u8>) -> bool {
+        self.step_over(search, b.map(|b| b as char), b.map_or(0, |_| 1))
+    }
+
+    fn step_over(&self, search: &mut Search<T>, c: Option<char>, width: usize) -> bool {
+        let pos = search.pos;
+        let mut clist = Vec::new();
+        let mut visited = HashSet::new();
+        for (state, slots) in search.clist.drain(..) {
+            self.add_thread(&mut clist, &mut visited, &state, slots, pos, (search.prev, c));
+        }
+        // A thread started after a recorded match can never win.
+        if search.matched.is_none() {
+            let mut slots = vec![None; self.slots];
+            slots[0] = Some(pos);
+            self.add_thread(&mut clist, &mut visited, &self.start_state, slots, pos, (search.prev, c));
+        }
+
+        let class = c.map(|c| self.classes.class_of(c));
+        let next_pos = pos + width;
+        let mut nlist = Vec::new();
+        for thread in clist {
+            match thread {
+                Thread::Match(mut slots) => {
+                    slots[1] = Some(pos);
+                    match self.kind {
+                        MatchKind::LeftmostFirst => {
+                            search.matched = Some(slots);
+                            // Lower priority threads are cut off.
+                            break;
+                        },
+                        MatchKind::LeftmostLongest => {
+                            // An earlier start can finish after a later
+                            // one, keep the leftmost and then the longest.
+                            let better = match search.matched {
+                                Some(ref m) => slots[0] < m[0] || (slots[0] == m[0] && slots[1] > m[1]),
+                                None => true,
+                            };
+                            if better {
+                                search.matched = Some(slots);
+                            }
+                        },
+                    }
+                },
+                Thread::Step(state, slots) => {
+                    let later = match search.matched {
+                        Some(ref m) => slots[0] > m[0],
+                        None => false,
+                    };
+                    if later {
+                        continue;
+                    }
+                    if let Some(class) = class {
+                        for (rule, reads) in self.rules_for(&state) {
+                            if reads[class] {
+                                nlist.push((rule.follow(), slots.clone()));
+                            }
+                        }
+                    }
+                },
+            }
+        }
+        // Once only a match is left `c` made no difference.
+        if c.is_none() || (nlist.is_empty() && search.matched.is_some()) {
+            return false;
+        }
+        search.clist = nlist;
+        search.prev = c;
+        search.pos = next_pos;
+        true
+    }
+
+    fn rules_for(&self, state: &T) -> &[Transition<T>] {
+        self.transitions.get(state).map_or(&[], |rules| rules.as_slice())
+    }
+
+    /// Add the thread at `state` and those its free moves lead to. `around`
+    /// holds the characters before and after `pos`.
+    fn add_thread(&self, list: &mut Vec<Thread<T>>, visited: &mut HashSet<T>,
+                  state: &T, slots: Slots, pos: usize, around: (Option<char>, Option<char>)) {
+        if !visited.insert(state.clone()) {
+            return;
+        }
+        let mut consumes = false;
+        for (rule, _) in self.rules_for(state) {
+            if let Some(look) = rule.look() {
+                if look.holds(around.0, around.1) {
+                    self.add_thread(list, visited, &rule.follow(), slots.clone(), pos, around);
+                }
+            } else if rule.is_free() {
+                let mut slots = slots.clone();
+                if let Some(tag) = rule.tag() {
+                    slots[tag] = Some(pos);
+                }
+                self.add_thread(list, visited, &rule.follow(), slots, pos, around);
+            } else {
+                consumes = true;
+            }
+        }
+        if consumes {
+            list.push(Thread::Step(state.clone(), slots.clone()));
+        }
+        if self.accept_states.contains(state) {
+            list.push(Thread::Match(slots));
+        }
+    }
+}
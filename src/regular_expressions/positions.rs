@@ -0,0 +1,162 @@
+use std::collections::HashSet;
+
+use finite_automata::farule::{FARule};
+use finite_automata::faruledata::{FARuleData};
+use finite_automata::look::{LineTerminator};
+use finite_automata::nfadesign::{NFADesign};
+use finite_automata::nfarulebook::{NFARulebook};
+use super::regex::{Regex};
+use super::state::{StateId};
+
+/// The position automaton of a pattern: every character, set or `.` of the
+/// pattern is a position, numbered from left to right, and reading a symbol
+/// moves from a position to one of the positions that can follow it.
+/// Capture groups are ignored. Lists of positions are in the order the
+/// Thompson automaton would reach them, so earlier ones have priority.
+#[derive(Debug,Clone,PartialEq)]
+pub struct Positions {
+    /// The symbols each position reads, as merged ranges.
+    pub symbols: Vec<Vec<FARuleData>>,
+    /// The positions a match can start with.
+    pub first: Vec<usize>,
+    /// The positions a match can end with.
+    pub last: Vec<usize>,
+    /// The positions that can come right after each position.
+    pub follow: Vec<Vec<usize>>,
+    /// Whether the empty string matches.
+    pub nullable: bool,
+}
+
+/// First, last and nullable of a subpattern.
+struct Node {
+    first: Vec<usize>,
+    last: Vec<usize>,
+    nullable: bool,
+}
+
+impl Positions {
+    /// `None` for patterns with anchors or raw bytes, which have no
+    /// position automaton.
+    pub fn new(regex: &Regex, terminator: LineTerminator) -> Option<Self> {
+        Positions::with_limit(regex, terminator, usize::MAX)
+    }
+
+    /// Like `new`, also `None` as soon as the pattern has more than `limit`
+    /// positions.
+    pub fn with_limit(regex: &Regex, terminator: LineTerminator, limit: usize) -> Option<Self> {
+        let mut positions = Positions {
+            symbols: Vec::new(),
+            first: Vec::new(),
+            last: Vec::new(),
+            follow: Vec::new(),
+            nullable: false,
+        };
+        let node = positions.visit(regex, terminator, limit)?;
+        positions.first = node.first;
+        positions.last = node.last;
+        positions.nullable = node.nullable;
+        Some(positions)
+    }
+
+    /// The number of positions.
+    pub fn len(&self) -> usize { self.symbols.len() }
+    pub fn is_empty(&self) -> bool { self.symbols.is_empty() }
+
+    /// Glushkov construction: state 0 starts, state `i + 1` is entered by
+    /// reading position `i`, so there are no free moves.
+    pub fn to_nfa_design(&self) -> NFADesign<StateId> {
+        let state = |position: usize| StateId(position as u32 + 1);
+        let mut rules = Vec::new();
+        let mut add_rules = |from: StateId, next: &[usize]| {
+            for &position in next {
+                let symbols = &self.symbols[position];
+                rules.push(match symbols.as_slice() {
+                    [only] if only.bounds().0 == only.bounds().1 => {
+                        FARule::new_rulechar(&from, only.bounds().0, &state(position))
+                    },
+                    _ => FARule::new_ruleset(&from, &state(position), symbols, false),
+                });
+            }
+        };
+        add_rules(StateId(0), &self.first);
+        for (position, next) in self.follow.iter().enumerate() {
+            add_rules(state(position), next);
+        }
+        let mut accept_states = self.last.iter().map(|&position| state(position)).collect::<HashSet<StateId>>();
+        if self.nullable {
+            accept_states.insert(StateId(0));
+        }
+        NFADesign::new(&StateId(0), &accept_states, &NFARulebook::new(rules))
+    }
+
+    fn position(&mut self, symbols: Vec<FARuleData>, limit: usize) -> Option<Node> {
+        let position = self.symbols.len();
+        if position >= limit {
+            return None;
+        }
+        self.symbols.push(symbols);
+        self.follow.push(Vec::new());
+        Some(Node { first: vec![position], last: vec![position], nullable: false })
+    }
+
+    fn visit(&mut self, regex: &Regex, terminator: LineTerminator, limit: usize) -> Option<Node> {
+        Some(match *regex {
+            Regex::Empty => Node { first: Vec::new(), last: Vec::new(), nullable: true },
+            Regex::Literal(c) => self.position(vec![FARuleData::char(c)], limit)?,
+            Regex::Set(ref set, false) => self.position(FARuleData::merge(set), limit)?,
+            Regex::Set(ref set, true) => self.position(FARuleData::complement(set), limit)?,
+            Regex::Any => self.position(vec![FARuleData::range('\0', ::std::char::MAX)], limit)?,
+            Regex::AnyButNewline => {
+                let terminators = terminator.characters().iter()
+                    .map(|&c| FARuleData::char(c))
+                    .collect::<Vec<FARuleData>>();
+                self.position(FARuleData::complement(&terminators), limit)?
+            },
+            Regex::Byte(_) | Regex::Anchor(_) => return None,
+            Regex::Concatenate(ref l, ref r) => {
+                let l = self.visit(l, terminator, limit)?;
+                let r = self.visit(r, terminator, limit)?;
+                for &x in l.last.iter() {
+                    extend(&mut self.follow[x], &r.first);
+                }
+                let mut first = l.first;
+                if l.nullable {
+                    extend(&mut first, &r.first);
+                }
+                let mut last = r.last;
+                if r.nullable {
+                    extend(&mut last, &l.last);
+                }
+                Node { first, last, nullable: l.nullable && r.nullable }
+            },
+            Regex::Choose(ref l, ref r) => {
+                let mut l = self.visit(l, terminator, limit)?;
+                let r = self.visit(r, terminator, limit)?;
+                extend(&mut l.first, &r.first);
+                extend(&mut l.last, &r.last);
+                Node { first: l.first, last: l.last, nullable: l.nullable || r.nullable }
+            },
+            Regex::Repeat(ref p) | Regex::Plus(ref p) | Regex::Optional(ref p) => {
+                let p_node = self.visit(p, terminator, limit)?;
+                let repeats = !matches!(*regex, Regex::Optional(_));
+                if repeats {
+                    for &x in p_node.last.iter() {
+                        extend(&mut self.follow[x], &p_node.first);
+                    }
+                }
+                let nullable = p_node.nullable || !matches!(*regex, Regex::Plus(_));
+                Node { first: p_node.first, last: p_node.last, nullable }
+            },
+            Regex::Group(_, ref p) => self.visit(p, terminator, limit)?,
+        })
+    }
+}
+
+/// Append the positions of `more` not already in `positions`.
+fn extend(positions: &mut Vec<usize>, more: &[usize]) {
+    for &position in more {
+        if !positions.contains(&position) {
+            positions.push(position);
+        }
+    }
+}
@@ -3,32 +3,140 @@ use std::fmt::Display;
 use std::fmt::Result;
 use std::fmt::Formatter;
 
+/// A zero-width position a pattern can require. The line anchors match
+/// around the line terminator set in the `CompileOptions`.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Anchor {
+    StartText,
+    EndText,
+    StartLine,
+    EndLine,
+}
+
 #[derive(Clone)]
 pub enum Regex {
     Empty,
     Literal(char),
+    Byte(u8),
     Set(Vec<FARuleData>, bool),
+    /// Any character, line terminators included.
     Any,
+    /// Any character but a line terminator.
+    AnyButNewline,
+    Anchor(Anchor),
     Concatenate(Box<Regex>, Box<Regex>),
     Choose(Box<Regex>, Box<Regex>),
     Repeat(Box<Regex>),
     Plus(Box<Regex>),
     Optional(Box<Regex>),
+    Group(Option<String>, Box<Regex>),
 }
 
 impl Regex {
     pub fn empty()-> Box<Regex> { Box::new(Regex::Empty) }
     pub fn literal(c: char)-> Box<Regex> { Box::new(Regex::Literal(c)) }
+    /// A raw byte when matching bytes, the character `b as char` otherwise.
+    pub fn byte(b: u8)-> Box<Regex> { Box::new(Regex::Byte(b)) }
+    #[allow(clippy::ptr_arg)]
     pub fn set(set: &Vec<FARuleData>, reverse: bool) -> Box<Regex> {
         Box::new(Regex::Set(set.clone(), reverse))
     }
     pub fn any() -> Box<Regex> { Box::new(Regex::Any) }
+    pub fn any_but_newline() -> Box<Regex> { Box::new(Regex::AnyButNewline) }
+    pub fn anchor(anchor: Anchor) -> Box<Regex> { Box::new(Regex::Anchor(anchor)) }
 
     pub fn concatenate(l: Box<Regex>, r: Box<Regex>)-> Box<Regex> { Box::new(Regex::Concatenate(l, r)) }
     pub fn choose(l: Box<Regex>, r: Box<Regex>)-> Box<Regex> { Box::new(Regex::Choose(l, r)) }
     pub fn repeat(p: Box<Regex>)-> Box<Regex> { Box::new(Regex::Repeat(p)) }
     pub fn plus(p: Box<Regex>)-> Box<Regex> { Box::new(Regex::Plus(p)) }
     pub fn optional(p: Box<Regex>)-> Box<Regex> { Box::new(Regex::Optional(p)) }
+    /// The concatenation of the characters of `s`.
+    pub fn literal_string(s: &str) -> Box<Regex> {
+        s.chars().map(Regex::literal).reduce(Regex::concatenate).unwrap_or_else(Regex::empty)
+    }
+    /// The choice between `branches`, in order of priority. The choices are
+    /// nested as a balanced tree, so even many branches nest shallowly.
+    pub fn alternation(mut branches: Vec<Box<Regex>>) -> Box<Regex> {
+        match branches.len() {
+            0 => Regex::empty(),
+            1 => branches.pop().unwrap(),
+            n => {
+                let right = branches.split_off(n / 2);
+                Regex::choose(Regex::alternation(branches), Regex::alternation(right))
+            },
+        }
+    }
+    pub fn group(p: Box<Regex>)-> Box<Regex> { Box::new(Regex::Group(None, p)) }
+    pub fn named_group(name: &str, p: Box<Regex>)-> Box<Regex> {
+        Box::new(Regex::Group(Some(name.to_string()), p))
+    }
+
+    /// Names of the capture groups, indexed by group number.
+    /// Group 0 is the whole match and is always unnamed.
+    pub fn capture_names(&self) -> Vec<Option<String>> {
+        let mut names = vec![None];
+        self.collect_capture_names(&mut names);
+        names
+    }
+
+    /// The strings of a choice between literal strings, in order of
+    /// priority, `None` for any other pattern.
+    pub fn literal_alternatives(&self) -> Option<Vec<String>> {
+        let mut alternatives = Vec::new();
+        match *self {
+            Regex::Choose(_, _) => self.collect_alternatives(&mut alternatives)?,
+            _ => return None,
+        }
+        Some(alternatives)
+    }
+
+    fn collect_alternatives(&self, alternatives: &mut Vec<String>) -> Option<()> {
+        match *self {
+            Regex::Choose(ref l, ref r) => {
+                l.collect_alternatives(alternatives)?;
+                r.collect_alternatives(alternatives)
+            },
+            _ => {
+                let mut literal = String::new();
+                self.collect_literal(&mut literal)?;
+                alternatives.push(literal);
+                Some(())
+            },
+        }
+    }
+
+    fn collect_literal(&self, literal: &mut String) -> Option<()> {
+        match *self {
+            Regex::Empty => Some(()),
+            Regex::Literal(c) => {
+                literal.push(c);
+                Some(())
+            },
+            Regex::Concatenate(ref l, ref r) => {
+                l.collect_literal(literal)?;
+                r.collect_literal(literal)
+            },
+            _ => None,
+        }
+    }
+
+    fn collect_capture_names(&self, names: &mut Vec<Option<String>>) {
+        match *self {
+            Regex::Empty | Regex::Literal(_) | Regex::Byte(_) | Regex::Any | Regex::Set(_,_) |
+            Regex::AnyButNewline | Regex::Anchor(_) => {},
+            Regex::Concatenate(ref l, ref r) | Regex::Choose(ref l, ref r) => {
+                l.collect_capture_names(names);
+                r.collect_capture_names(names);
+            },
+            Regex::Repeat(ref p) | Regex::Plus(ref p) | Regex::Optional(ref p) => {
+                p.collect_capture_names(names);
+            },
+            Regex::Group(ref name, ref p) => {
+                names.push(name.clone());
+                p.collect_capture_names(names);
+            },
+        }
+    }
 
     fn bracket(&self, outer_precedence: u32) -> String {
         if self.precedence() < outer_precedence {
@@ -40,7 +148,8 @@ impl Regex {
 
     fn precedence(&self) -> u32 {
         match *self {
-            Regex::Empty | Regex::Literal(_) | Regex::Any | Regex::Set(_,_) => 3,
+            Regex::Empty | Regex::Literal(_) | Regex::Byte(_) | Regex::Any | Regex::Set(_,_) |
+            Regex::AnyButNewline | Regex::Anchor(_) | Regex::Group(_,_) => 3,
             Regex::Concatenate(_,_) => 1,
             Regex::Choose(_,_) => 0,
             Regex::Repeat(_) | Regex::Plus(_) | Regex::Optional(_) => 2,
@@ -53,15 +162,23 @@ impl Display for Regex {
         match *self {
             Regex::Empty => write!(f, ""),
             Regex::Literal(s) => write!(f, "{}", s),
+            Regex::Byte(b) => write!(f, "\\x{:02X}", b),
             Regex::Set(ref set, reverse) => write!(f, "[{}{}]",
                 if reverse {"^"} else {""},
                 set.iter().map(|data| format!("{}", data)).collect::<Vec<String>>().join("")),
-            Regex::Any => write!(f, "."),
+            Regex::Any => write!(f, "(?s:.)"),
+            Regex::AnyButNewline => write!(f, "."),
+            Regex::Anchor(Anchor::StartText) => write!(f, "^"),
+            Regex::Anchor(Anchor::EndText) => write!(f, "$"),
+            Regex::Anchor(Anchor::StartLine) => write!(f, "(?m:^)"),
+            Regex::Anchor(Anchor::EndLine) => write!(f, "(?m:$)"),
             Regex::Concatenate(ref l, ref r) => write!(f, "{}", [l, r].iter().map(|pat| pat.bracket(self.precedence())).collect::<Vec<String>>().join("")),
             Regex::Choose(ref l, ref r) => write!(f, "{}", [l, r].iter().map(|pat| pat.bracket(self.precedence())).collect::<Vec<String>>().join("|")),
             Regex::Repeat(ref p) => write!(f, "{}*", p.bracket(self.precedence())),
             Regex::Plus(ref p) => write!(f, "{}+", p.bracket(self.precedence())),
             Regex::Optional(ref p) => write!(f, "{}?", p.bracket(self.precedence())),
+            Regex::Group(None, ref p) => write!(f, "({})", p),
+            Regex::Group(Some(ref name), ref p) => write!(f, "(?P<{}>{})", name, p),
         }
     }
 }
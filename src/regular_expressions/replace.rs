@@ -0,0 +1,63 @@
+use std::borrow::Cow;
+
+use super::captures::{Captures};
+
+/// Produces the replacement text for a match.
+///
+/// Implemented for template strings, which are expanded with
+/// `Captures::expand`, and for closures `FnMut(&Captures) -> String`.
+pub trait Replacer {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String);
+
+    /// The replacement when it does not depend on the match.
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        None
+    }
+}
+
+impl Replacer for &str {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        if self.contains('$') {
+            None
+        } else {
+            Some(Cow::Borrowed(self))
+        }
+    }
+}
+
+impl Replacer for &String {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        caps.expand(self, dst);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        if self.contains('$') {
+            None
+        } else {
+            Some(Cow::Borrowed(self.as_str()))
+        }
+    }
+}
+
+impl<F> Replacer for F where F: FnMut(&Captures) -> String {
+    fn replace_append(&mut self, caps: &Captures, dst: &mut String) {
+        dst.push_str(&self(caps));
+    }
+}
+
+/// Use the string as the replacement verbatim, without expanding `$`.
+pub struct NoExpand<'t>(pub &'t str);
+
+impl<'t> Replacer for NoExpand<'t> {
+    fn replace_append(&mut self, _: &Captures, dst: &mut String) {
+        dst.push_str(self.0);
+    }
+
+    fn no_expansion(&mut self) -> Option<Cow<'_, str>> {
+        Some(Cow::Borrowed(self.0))
+    }
+}
@@ -0,0 +1,113 @@
+use finite_automata::classes::{SymbolClasses};
+use finite_automata::farule::{FARule};
+use super::positions::{Positions};
+
+/// The most positions a `ShiftAnd` can hold, one per bit of its state.
+pub const MAX_POSITIONS: usize = 64;
+
+/// Simulates the position automaton of a small pattern with one bit per
+/// position, so a step is a few table lookups whatever the number of active
+/// positions. Only reports where matches end.
+#[derive(Debug,Clone)]
+pub struct ShiftAnd {
+    classes: SymbolClasses,
+    /// The positions reading each symbol class.
+    masks: Vec<u64>,
+    /// For each byte of the state, the positions following the positions
+    /// set in it.
+    follow: Vec<[u64; 256]>,
+    first: u64,
+    last: u64,
+    nullable: bool,
+}
+
+impl ShiftAnd {
+    /// `None` when the pattern has more than `MAX_POSITIONS` positions.
+    pub fn new(positions: &Positions) -> Option<Self> {
+        if positions.len() > MAX_POSITIONS {
+            return None;
+        }
+        let rules = positions.symbols.iter().enumerate()
+            .map(|(i, symbols)| FARule::new_ruleset(&i, &i, symbols, false))
+            .collect::<Vec<FARule<usize>>>();
+        let classes = SymbolClasses::new(&rules);
+        let masks = (0..classes.len())
+            .map(|class| {
+                let c = classes.representative(class);
+                rules.iter().filter(|rule| rule.applies_to(&rule.state, Some(c)))
+                     .fold(0, |mask, rule| mask | 1 << rule.state)
+            })
+            .collect();
+        // One table per byte of the state that can have positions set.
+        let mut follow = vec![[0u64; 256]; (positions.len() + 7) / 8];
+        for (chunk, table) in follow.iter_mut().enumerate() {
+            for (byte, entry) in table.iter_mut().enumerate() {
+                for bit in (0..8).filter(|bit| byte & (1 << bit) != 0) {
+                    if let Some(next) = positions.follow.get(chunk * 8 + bit) {
+                        *entry |= bits(next);
+                    }
+                }
+            }
+        }
+        Some(ShiftAnd {
+            classes,
+            masks,
+            follow,
+            first: bits(&positions.first),
+            last: bits(&positions.last),
+            nullable: positions.nullable,
+        })
+    }
+
+    /// The positions after reading `c` from the positions `state`, with a
+    /// new match starting at `c` when `start` is set.
+    fn step(&self, state: u64, c: char, start: bool) -> u64 {
+        let mut next = if start { self.first } else { 0 };
+        for (chunk, table) in self.follow.iter().enumerate() {
+            next |= table[((state >> (8 * chunk)) & 0xff) as usize];
+        }
+        next & self.masks[self.classes.class_of(c)]
+    }
+
+    /// Whether the whole of `s` matches.
+    pub fn accept(&self, s: &str) -> bool {
+        let mut chars = s.chars();
+        let mut state = match chars.next() {
+            Some(c) => self.step(0, c, true),
+            None => return self.nullable,
+        };
+        for c in chars {
+            if state == 0 {
+                return false;
+            }
+            state = self.step(state, c, false);
+        }
+        state & self.last != 0
+    }
+
+    /// Whether some substring of `s` matches.
+    pub fn is_match(&self, s: &str) -> bool {
+        self.shortest_match(s).is_some()
+    }
+
+    /// The end of the first match to complete, with the match allowed to
+    /// start anywhere.
+    pub fn shortest_match(&self, s: &str) -> Option<usize> {
+        if self.nullable {
+            return Some(0);
+        }
+        let mut state = 0;
+        for (i, c) in s.char_indices() {
+            state = self.step(state, c, true);
+            if state & self.last != 0 {
+                return Some(i + c.len_utf8());
+            }
+        }
+        None
+    }
+}
+
+/// The state with the bits of `positions` set.
+fn bits(positions: &[usize]) -> u64 {
+    positions.iter().fold(0, |mask, &i| mask | 1 << i)
+}
@@ -1,21 +1,41 @@
 use std::fmt::Display;
 use std::fmt::Formatter;
 use std::fmt::Result;
-use std::rc::Rc;
 
-#[derive(Eq, Hash)]
-pub struct State;
-pub type RCState = Rc<State>;
+/// A state of an automaton built from a `Regex`, the index it was given by
+/// the `StateArena` of the construction.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord,Hash)]
+pub struct StateId(pub u32);
 
-impl PartialEq for State {
-    fn eq(&self, rhs: &Self) -> bool {
-        self as *const _ == rhs as *const _
-    }
+impl StateId {
+    pub fn index(self) -> usize { self.0 as usize }
 }
 
-impl Display for State {
+impl Display for StateId {
     fn fmt(&self, f: &mut Formatter) -> Result {
-        write!(f, "{}", self as *const _ as usize)
+        write!(f, "{}", self.0)
     }
 }
 
+/// Hands out the states of one construction, numbered densely from 0 in
+/// the order they are created.
+#[derive(Debug,Default)]
+pub struct StateArena {
+    len: u32,
+}
+
+impl StateArena {
+    pub fn new() -> Self {
+        StateArena { len: 0 }
+    }
+
+    pub fn state(&mut self) -> StateId {
+        let state = StateId(self.len);
+        self.len += 1;
+        state
+    }
+
+    /// Number of states created so far.
+    pub fn len(&self) -> usize { self.len as usize }
+    pub fn is_empty(&self) -> bool { self.len == 0 }
+}
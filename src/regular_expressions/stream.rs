@@ -0,0 +1,184 @@
+use std::collections::VecDeque;
+use std::io;
+use std::io::Read;
+use std::ops::Range;
+use std::str;
+
+use super::compiled::{CompiledRegex};
+use super::pikevm::{Search};
+use super::state::{StateId};
+
+/// Finds the successive non-overlapping matches of a `CompiledRegex` in
+/// input that arrives in chunks of bytes. Match offsets count bytes from the
+/// start of the whole stream, and a UTF-8 sequence may be split between two
+/// chunks.
+///
+/// Only the characters read after the current tentative match ended are
+/// kept, they are searched again once the match is confirmed.
+pub struct StreamMatcher<'r> {
+    regex: &'r CompiledRegex,
+    search: Search<StateId>,
+    unconfirmed: Vec<char>,
+    last_match: Option<usize>,
+    // The character before the end of the tentative match.
+    before_end: Option<char>,
+    partial: Vec<u8>,
+    finished: bool,
+}
+
+impl<'r> StreamMatcher<'r> {
+    pub fn new(regex: &'r CompiledRegex) -> Self {
+        StreamMatcher {
+            regex,
+            search: regex.vm().start(0, None),
+            unconfirmed: Vec::new(),
+            last_match: None,
+            before_end: None,
+            partial: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Read the next chunk, returning the matches it completed.
+    pub fn feed(&mut self, chunk: &[u8]) -> io::Result<Vec<Range<usize>>> {
+        let mut bytes = ::std::mem::take(&mut self.partial);
+        bytes.extend_from_slice(chunk);
+        let valid = match str::from_utf8(&bytes) {
+            Ok(_) => bytes.len(),
+            Err(e) => match e.error_len() {
+                Some(_) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+                None => e.valid_up_to(),
+            },
+        };
+        self.partial = bytes.split_off(valid);
+        let text = str::from_utf8(&bytes).unwrap();
+        let mut matches = Vec::new();
+        self.read(text.chars().map(Some).collect(), &mut matches);
+        Ok(matches)
+    }
+
+    /// Signal the end of the stream, returning the matches that were still
+    /// waiting for more input.
+    pub fn finish(&mut self) -> io::Result<Vec<Range<usize>>> {
+        if !self.partial.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "stream ended inside a UTF-8 sequence"));
+        }
+        let mut matches = Vec::new();
+        let mut input = VecDeque::new();
+        input.push_back(None);
+        self.read(input, &mut matches);
+        Ok(matches)
+    }
+
+    fn read(&mut self, mut input: VecDeque<Option<char>>, matches: &mut Vec<Range<usize>>) {
+        let vm = self.regex.vm();
+        while let Some(c) = input.pop_front() {
+            if self.finished {
+                return;
+            }
+            let end = self.search.matched().map(|slots| slots[1]);
+            let prev = self.search.prev();
+            let reading = vm.step(&mut self.search, c);
+            if self.search.matched().map(|slots| slots[1]) != end {
+                // A longer match was found, what it covers is confirmed.
+                self.unconfirmed.clear();
+                self.before_end = prev;
+            }
+            if reading {
+                if self.search.matched().is_some() {
+                    self.unconfirmed.extend(c);
+                }
+                continue;
+            }
+
+            // The search is over and left `c` unread.
+            let mut replay = ::std::mem::take(&mut self.unconfirmed);
+            replay.extend(c);
+            let (next_start, prev) = match self.search.matched().map(|slots| (slots[0].unwrap(), slots[1].unwrap())) {
+                Some((start, end)) => {
+                    if start != end || Some(end) != self.last_match {
+                        matches.push(start..end);
+                        self.last_match = Some(end);
+                    }
+                    if start != end {
+                        (end, self.before_end)
+                    } else if replay.is_empty() {
+                        // An empty match at the end of the stream.
+                        self.finished = true;
+                        return;
+                    } else {
+                        let skipped = replay.remove(0);
+                        (end + skipped.len_utf8(), Some(skipped))
+                    }
+                },
+                None => match c {
+                    // Nothing can start here, so move past `c`.
+                    Some(c) => {
+                        replay.clear();
+                        (self.search.pos() + c.len_utf8(), Some(c))
+                    },
+                    None => {
+                        self.finished = true;
+                        return;
+                    },
+                },
+            };
+            self.search = vm.start(next_start, prev);
+            if c.is_none() {
+                input.push_front(None);
+            }
+            for &c in replay.iter().rev() {
+                input.push_front(Some(c));
+            }
+        }
+    }
+}
+
+/// Iterator over the matches of a `CompiledRegex` in everything a reader
+/// produces.
+pub struct ReadMatches<'r, R> {
+    matcher: StreamMatcher<'r>,
+    reader: R,
+    buffer: Vec<u8>,
+    ready: VecDeque<Range<usize>>,
+    eof: bool,
+}
+
+impl<'r, R: Read> ReadMatches<'r, R> {
+    pub fn new(regex: &'r CompiledRegex, reader: R) -> Self {
+        ReadMatches {
+            matcher: StreamMatcher::new(regex),
+            reader,
+            buffer: vec![0; 8192],
+            ready: VecDeque::new(),
+            eof: false,
+        }
+    }
+}
+
+impl<'r, R: Read> Iterator for ReadMatches<'r, R> {
+    type Item = io::Result<Range<usize>>;
+
+    fn next(&mut self) -> Option<io::Result<Range<usize>>> {
+        while self.ready.is_empty() && !self.eof {
+            let matches = match self.reader.read(&mut self.buffer) {
+                Ok(0) => {
+                    self.eof = true;
+                    self.matcher.finish()
+                },
+                Ok(n) => self.matcher.feed(&self.buffer[..n]),
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => Err(e),
+            };
+            match matches {
+                Ok(matches) => self.ready.extend(matches),
+                Err(e) => {
+                    self.eof = true;
+                    return Some(Err(e));
+                },
+            }
+        }
+        self.ready.pop_front().map(Ok)
+    }
+}
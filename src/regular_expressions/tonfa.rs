@@ -1,138 +1,311 @@
-use std::rc::Rc;
+use std::collections::HashSet;
 
 use helper::{to_hashset};
 
 use finite_automata::farule::{FARule};
+use finite_automata::faruledata::{FARuleData};
+use finite_automata::look::{LineTerminator, Look};
 use finite_automata::nfadesign::{NFADesign};
 use finite_automata::nfarulebook::{NFARulebook};
-use super::regex::{Regex};
-use super::state::{State, RCState};
+use super::positions::{Positions};
+use super::regex::{Anchor, Regex};
+use super::state::{StateArena, StateId};
+use super::utf8::{utf8_sequences};
 
 pub trait ToNFA {
-    fn to_nfa_design(&self) -> NFADesign<RCState>;
+    fn to_nfa_design(&self) -> NFADesign<StateId>;
+    /// An automaton over bytes, where the byte `b` is read as the character
+    /// `b as char`. Characters of the pattern match their UTF-8 encoding.
+    fn to_byte_nfa_design(&self) -> NFADesign<StateId>;
+    /// Like `to_nfa_design`, with `terminator` ending lines for `.` and the
+    /// line anchors instead of `\n`.
+    fn to_nfa_design_with(&self, terminator: LineTerminator) -> NFADesign<StateId>;
+    fn to_byte_nfa_design_with(&self, terminator: LineTerminator) -> NFADesign<StateId>;
+    /// The epsilon-free Glushkov automaton, `None` for patterns with anchors
+    /// or bytes. Capture groups are not recorded.
+    fn to_glushkov_nfa_design_with(&self, terminator: LineTerminator) -> Option<NFADesign<StateId>>;
+    #[allow(anonymous_parameters)]
     fn matches(&self, &str) -> bool;
 }
 
 impl ToNFA for Regex {
-    fn to_nfa_design(&self) -> NFADesign<RCState> {
+    fn to_nfa_design(&self) -> NFADesign<StateId> {
+        self.to_nfa_design_with(LineTerminator::LF)
+    }
+
+    fn to_byte_nfa_design(&self) -> NFADesign<StateId> {
+        self.to_byte_nfa_design_with(LineTerminator::LF)
+    }
+
+    fn to_nfa_design_with(&self, terminator: LineTerminator) -> NFADesign<StateId> {
+        self.build_fragment(&mut Builder::new(false, terminator)).into_design()
+    }
+
+    fn to_byte_nfa_design_with(&self, terminator: LineTerminator) -> NFADesign<StateId> {
+        self.build_fragment(&mut Builder::new(true, terminator)).into_design()
+    }
+
+    fn to_glushkov_nfa_design_with(&self, terminator: LineTerminator) -> Option<NFADesign<StateId>> {
+        Positions::new(self, terminator).map(|positions| positions.to_nfa_design())
+    }
+
+    fn matches(&self, s: &str) -> bool {
+        self.to_nfa_design().accept(s)
+    }
+}
+
+/// A piece of automaton under construction. The rulebook is only built
+/// once the whole pattern is, see `into_design`.
+struct Fragment {
+    start_state: StateId,
+    accept_states: HashSet<StateId>,
+    rules: Vec<FARule<StateId>>,
+}
+
+impl Fragment {
+    fn new(start_state: &StateId, accept_states: HashSet<StateId>, rules: Vec<FARule<StateId>>) -> Self {
+        Fragment {
+            start_state: *start_state,
+            accept_states,
+            rules,
+        }
+    }
+
+    fn into_design(self) -> NFADesign<StateId> {
+        NFADesign::new(&self.start_state, &self.accept_states, &NFARulebook::new(self.rules))
+    }
+}
+
+/// What a construction keeps track of while it walks the pattern.
+struct Builder {
+    states: StateArena,
+    next_group: usize,
+    bytes: bool,
+    terminator: LineTerminator,
+}
+
+impl Builder {
+    fn new(bytes: bool, terminator: LineTerminator) -> Self {
+        Builder {
+            states: StateArena::new(),
+            next_group: 1,
+            bytes,
+            terminator,
+        }
+    }
+}
+
+impl Regex {
+    /// Thompson construction. Capture groups are numbered in the order
+    /// their opening bracket appears, group `i` records its span with
+    /// tags `2 * i` and `2 * i + 1`.
+    fn build_fragment(&self, builder: &mut Builder) -> Fragment {
         match *self {
+            Regex::Literal(c) if builder.bytes => {
+                Regex::utf8_fragment(builder, &[FARuleData::char(c)])
+            },
+            Regex::Set(ref set, reverse) if builder.bytes => {
+                if reverse {
+                    Regex::utf8_fragment(builder, &FARuleData::complement(set))
+                } else {
+                    Regex::utf8_fragment(builder, set)
+                }
+            },
+            Regex::Any if builder.bytes => {
+                Regex::utf8_fragment(builder, &[FARuleData::range('\0', ::std::char::MAX)])
+            },
+            Regex::AnyButNewline => {
+                let set = builder.terminator.characters().iter()
+                    .map(|&c| FARuleData::char(c))
+                    .collect::<Vec<FARuleData>>();
+                Regex::set(&set, true).build_fragment(builder)
+            },
             Regex::Empty => {
-                let start_state = Rc::new(State{});
-                NFADesign::new(
+                let start_state = builder.states.state();
+                Fragment::new(
                     &start_state,
-                    &to_hashset(&[start_state.clone()]),
-                    &NFARulebook::new(vec![])
+                    to_hashset(&[start_state]),
+                    vec![]
                 )
             },
             Regex::Literal(c) => {
-                let start_state = Rc::new(State{});
-                let accept_state = Rc::new(State{});
+                let start_state = builder.states.state();
+                let accept_state = builder.states.state();
                 let rule = FARule::new_rulechar(&start_state, c, &accept_state);
-                NFADesign::new(
+                Fragment::new(
+                    &start_state,
+                    to_hashset(&[accept_state]),
+                    vec![rule],
+                )
+            },
+            Regex::Byte(b) => {
+                let start_state = builder.states.state();
+                let accept_state = builder.states.state();
+                let rule = FARule::new_rulechar(&start_state, b as char, &accept_state);
+                Fragment::new(
                     &start_state,
-                    &to_hashset(&[accept_state]),
-                    &NFARulebook::new(vec![rule]),
+                    to_hashset(&[accept_state]),
+                    vec![rule],
                 )
             },
             Regex::Set(ref set, reverse) => {
-                let start_state = Rc::new(State{});
-                let accept_state = Rc::new(State{});
+                let start_state = builder.states.state();
+                let accept_state = builder.states.state();
                 let rule = FARule::new_ruleset(&start_state, &accept_state, set, reverse);
-                NFADesign::new(
+                Fragment::new(
                     &start_state,
-                    &to_hashset(&[accept_state]),
-                    &NFARulebook::new(vec![rule]),
+                    to_hashset(&[accept_state]),
+                    vec![rule],
                 )
             }
             Regex::Any => {
-                let start_state = Rc::new(State{});
-                let accept_state = Rc::new(State{});
+                let start_state = builder.states.state();
+                let accept_state = builder.states.state();
                 let rule = FARule::new_ruleany(&start_state, &accept_state);
-                NFADesign::new(
+                Fragment::new(
                     &start_state,
-                    &to_hashset(&[accept_state]),
-                    &NFARulebook::new(vec![rule]),
+                    to_hashset(&[accept_state]),
+                    vec![rule],
                 )
             }
+            Regex::Anchor(anchor) => {
+                let start_state = builder.states.state();
+                let accept_state = builder.states.state();
+                let look = match anchor {
+                    Anchor::StartText => Look::StartText,
+                    Anchor::EndText => Look::EndText,
+                    Anchor::StartLine => Look::StartLine(builder.terminator),
+                    Anchor::EndLine => Look::EndLine(builder.terminator),
+                };
+                let rule = FARule::new_rulelook(&start_state, look, &accept_state);
+                Fragment::new(
+                    &start_state,
+                    to_hashset(&[accept_state]),
+                    vec![rule],
+                )
+            },
             Regex::Concatenate(ref l, ref r) => {
-                let first = l.to_nfa_design();
-                let second = r.to_nfa_design();
-                let start_state = first.start_state();
-                let accept_state = second.accept_state();
-                let mut rule1 = first.rules();
-                let rule2 = second.rules();
-                let extrarules = first.accept_state().iter()
-                    .map(|state| FARule::new_rulefree(state, &second.start_state()))
-                    .collect::<Vec<FARule<RCState>>>();
-                rule1.extend_from_slice(&rule2);
-                rule1.extend_from_slice(&extrarules);
-                NFADesign::new(
+                let first = l.build_fragment(builder);
+                let second = r.build_fragment(builder);
+                let start_state = first.start_state;
+                let accept_state = second.accept_states;
+                let second_start = second.start_state;
+                let mut rule1 = first.rules;
+                let extrarules = sorted(&first.accept_states).iter()
+                    .map(|state| FARule::new_rulefree(state, &second_start))
+                    .collect::<Vec<FARule<StateId>>>();
+                rule1.extend(second.rules);
+                rule1.extend(extrarules);
+                Fragment::new(
                     &start_state,
-                    &accept_state,
-                    &NFARulebook::new(rule1))
+                    accept_state,
+                    rule1)
             },
             Regex::Choose(ref l, ref r) => {
-                let first = l.to_nfa_design();
-                let second = r.to_nfa_design();
-                let start_state = Rc::new(State{});
-                let accept_state = first.accept_state().union(&second.accept_state()).cloned().collect();
-                let mut rules = first.rules();
-                rules.extend_from_slice(&second.rules());
+                let first = l.build_fragment(builder);
+                let second = r.build_fragment(builder);
+                let start_state = builder.states.state();
+                let accept_state = first.accept_states.union(&second.accept_states).cloned().collect();
+                let mut rules = first.rules;
+                rules.extend(second.rules);
                 rules.extend_from_slice(&[
-                    FARule::new_rulefree(&start_state, &first.start_state()),
-                    FARule::new_rulefree(&start_state, &second.start_state())]);
-                NFADesign::new(
+                    FARule::new_rulefree(&start_state, &first.start_state),
+                    FARule::new_rulefree(&start_state, &second.start_state)]);
+                Fragment::new(
                     &start_state,
-                    &accept_state,
-                    &NFARulebook::new(rules))
+                    accept_state,
+                    rules)
             },
             Regex::Repeat(ref p) => {
-                let pattern_nfa = p.to_nfa_design();
-                let start_state = Rc::new(State{});
-                let mut accept_state = pattern_nfa.accept_state();
-                accept_state.insert(start_state.clone());
+                let pattern_nfa = p.build_fragment(builder);
+                let start_state = builder.states.state();
+                let mut accept_state = pattern_nfa.accept_states;
+                accept_state.insert(start_state);
 
-                let mut rules = pattern_nfa.rules();
-                rules.extend(accept_state.iter().map(|state| FARule::new_rulefree(state, &pattern_nfa.start_state())));
+                let pattern_start = pattern_nfa.start_state;
+                let mut rules = pattern_nfa.rules;
+                rules.extend(sorted(&accept_state).iter().map(|state| FARule::new_rulefree(state, &pattern_start)));
 
-                NFADesign::new(
+                Fragment::new(
                     &start_state,
-                    &accept_state,
-                    &NFARulebook::new(rules))
+                    accept_state,
+                    rules)
             },
             Regex::Plus(ref p) => {
-                let pattern_nfa = p.to_nfa_design();
-                let start_state = Rc::new(State{});
-                let mut accept_state = pattern_nfa.accept_state();
+                let pattern_nfa = p.build_fragment(builder);
+                let start_state = builder.states.state();
+                let accept_state = pattern_nfa.accept_states;
 
-                let mut rules = pattern_nfa.rules();
-                rules.extend(accept_state.iter().map(|state| FARule::new_rulefree(state, &pattern_nfa.start_state())));
-                rules.push(FARule::new_rulefree(&start_state, &pattern_nfa.start_state()));
+                let pattern_start = pattern_nfa.start_state;
+                let mut rules = pattern_nfa.rules;
+                rules.extend(sorted(&accept_state).iter().map(|state| FARule::new_rulefree(state, &pattern_start)));
+                rules.push(FARule::new_rulefree(&start_state, &pattern_start));
 
-                NFADesign::new(
+                Fragment::new(
                     &start_state,
-                    &accept_state,
-                    &NFARulebook::new(rules))
+                    accept_state,
+                    rules)
             },
             Regex::Optional(ref p) => {
-                let pattern_nfa = p.to_nfa_design();
-                let start_state = Rc::new(State{});
-                let mut accept_state = pattern_nfa.accept_state();
-                accept_state.insert(start_state.clone());
-                let mut rules = pattern_nfa.rules();
-                rules.push(FARule::new_rulefree(&start_state, &pattern_nfa.start_state()));
-
-                NFADesign::new(
+                let pattern_nfa = p.build_fragment(builder);
+                let start_state = builder.states.state();
+                let mut accept_state = pattern_nfa.accept_states;
+                accept_state.insert(start_state);
+                let mut rules = pattern_nfa.rules;
+                rules.push(FARule::new_rulefree(&start_state, &pattern_nfa.start_state));
+
+                Fragment::new(
+                    &start_state,
+                    accept_state,
+                    rules)
+            },
+            Regex::Group(_, ref p) => {
+                let group = builder.next_group;
+                builder.next_group += 1;
+                let pattern_nfa = p.build_fragment(builder);
+                let start_state = builder.states.state();
+                let accept_state = builder.states.state();
+                let mut rules = pattern_nfa.rules;
+                rules.push(FARule::new_ruletag(&start_state, 2 * group, &pattern_nfa.start_state));
+                rules.extend(sorted(&pattern_nfa.accept_states).iter()
+                    .map(|state| FARule::new_ruletag(state, 2 * group + 1, &accept_state)));
+
+                Fragment::new(
                     &start_state,
-                    &accept_state,
-                    &NFARulebook::new(rules))
+                    to_hashset(&[accept_state]),
+                    rules)
             },
         }
     }
 
-    fn matches(&self, s: &str) -> bool {
-        match *self {
-            _ => self.to_nfa_design().accept(s)
+    /// Read the UTF-8 encoding of any character in `ranges` byte by byte.
+    fn utf8_fragment(builder: &mut Builder, ranges: &[FARuleData]) -> Fragment {
+        let start_state = builder.states.state();
+        let accept_state = builder.states.state();
+        let mut rules = Vec::new();
+        for sequence in utf8_sequences(ranges) {
+            let mut state = start_state;
+            for (i, data) in sequence.iter().enumerate() {
+                let next_state = if i + 1 == sequence.len() {
+                    accept_state
+                } else {
+                    builder.states.state()
+                };
+                rules.push(FARule::new_ruleset(&state, &next_state, &vec![data.clone()], false));
+                state = next_state;
+            }
         }
+        Fragment::new(
+            &start_state,
+            to_hashset(&[accept_state]),
+            rules)
     }
 }
+
+/// The states in order of creation, so the rules built from a set of states
+/// come out in the same order every time.
+fn sorted(states: &HashSet<StateId>) -> Vec<StateId> {
+    let mut states = states.iter().cloned().collect::<Vec<StateId>>();
+    states.sort();
+    states
+}
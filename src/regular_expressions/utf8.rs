@@ -0,0 +1,65 @@
+use finite_automata::faruledata::{FARuleData};
+
+/// Lower character ranges to the UTF-8 byte sequences that encode them.
+/// Every sequence is a list of byte ranges, one per byte of the encoding,
+/// with a byte `b` written as the character `b as char`.
+pub fn utf8_sequences(ranges: &[FARuleData]) -> Vec<Vec<FARuleData>> {
+    let mut sequences = Vec::new();
+    for data in FARuleData::merge(ranges) {
+        let (start, end) = data.bounds();
+        split(start as u32, end as u32, &mut sequences);
+    }
+    sequences
+}
+
+fn split(start: u32, end: u32, sequences: &mut Vec<Vec<FARuleData>>) {
+    // Surrogates have no encoding.
+    if start <= 0xDFFF && end >= 0xD800 {
+        if start < 0xD800 {
+            split(start, 0xD7FF, sequences);
+        }
+        if end > 0xDFFF {
+            split(0xE000, end, sequences);
+        }
+        return;
+    }
+    // Both ends must have encodings of the same length.
+    for &max in &[0x7F, 0x7FF, 0xFFFF] {
+        if start <= max && max < end {
+            split(start, max, sequences);
+            split(max + 1, end, sequences);
+            return;
+        }
+    }
+    // The continuation bytes must cover their whole range, except in the
+    // last position where start and end differ.
+    for i in 1..4 {
+        let mask = (1 << (6 * i)) - 1;
+        if start & !mask != end & !mask {
+            if start & mask != 0 {
+                split(start, start | mask, sequences);
+                split((start | mask) + 1, end, sequences);
+                return;
+            }
+            if end & mask != mask {
+                split(start, (end & !mask) - 1, sequences);
+                split(end & !mask, end, sequences);
+                return;
+            }
+        }
+    }
+    let start = encode(start);
+    let end = encode(end);
+    sequences.push(start.iter().zip(end.iter())
+        .map(|(&lo, &hi)| if lo == hi {
+            FARuleData::char(lo as char)
+        } else {
+            FARuleData::range(lo as char, hi as char)
+        })
+        .collect());
+}
+
+fn encode(c: u32) -> Vec<u8> {
+    let mut buffer = [0; 4];
+    ::std::char::from_u32(c).unwrap().encode_utf8(&mut buffer).as_bytes().to_vec()
+}